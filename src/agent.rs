@@ -1,18 +1,28 @@
 use std::{
-    cmp::{max, min},
+    cmp::max,
     collections::HashMap,
-    i32,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc, LazyLock, Mutex,
+        Arc, Mutex,
     },
-    thread::sleep,
-    time::Duration,
+    thread::{self, sleep},
+    time::{Duration, Instant},
 };
 
 use crate::{
-    board::{get_confirm_stone, get_valid_moves, put, Board, BoardView},
-    popcnt64, tzcnt64, write_log,
+    baseline::{FixedDepthAgent, GreedyAgent, RandomAgent},
+    board::{
+        format_line, get_confirm_stone, get_valid_moves, put, to_notation, Board, BoardView, PASS,
+    },
+    clock::{SearchMonitor, TimeLimit},
+    mcts::{MctsAgent, MctsOptions},
+    ponder::Ponder,
+    popcnt64,
+    probcut::{load_probcut, ProbCut},
+    proto::{Color, Error, GameResult},
+    stats::SearchStats,
+    tt::{self, Bound, Entry, TranspositionTable},
+    tzcnt64, write_log, Args, Engine,
 };
 
 #[inline]
@@ -20,7 +30,7 @@ pub fn evaluate(board: Board) -> i32 {
     let pl = popcnt64!(board.player);
     let op = popcnt64!(board.opponent);
     if pl + op > 60 {
-        (pl - op) * 64
+        (pl - op) * VALUE_PER_DISC
     } else {
         (get_confirm_stone(board.player) - get_confirm_stone(board.opponent)) * 64
             + (popcnt64!(board.player & 0x8100000000000081)
@@ -44,58 +54,176 @@ pub fn evaluate(board: Board) -> i32 {
     }
 }
 
-const INF: i32 = i32::MAX - 100;
+pub const INF: i32 = i32::MAX - 100;
 
-pub fn alpha_beta(
-    interrupt: &Arc<AtomicBool>,
-    board: Board,
-    player: bool,
-    depth: u8,
-    alpha: i32,
-    beta: i32,
-) -> Result<i32, ()> {
-    if interrupt.load(Ordering::Relaxed) {
-        return Err(());
+///
+/// The evaluation of a disc at the end of the game.
+///
+pub const VALUE_PER_DISC: i32 = 64;
+
+///
+/// The number of nodes between the checks of the deadline.
+///
+pub const DEADLINE_INTERVAL: u64 = 256;
+
+///
+/// A search context shared by every node of a search.
+///
+pub struct Searcher {
+    interrupt: Arc<AtomicBool>,
+    probcut: Option<Arc<ProbCut>>,
+    tt: Option<Arc<TranspositionTable>>,
+    ///
+    /// The time the search is interrupted at, which also flips `interrupt` for the other searches.
+    ///
+    deadline: Option<Instant>,
+    ply: u8,
+    pub stats: SearchStats,
+}
+
+impl Searcher {
+    pub fn new(
+        interrupt: Arc<AtomicBool>,
+        probcut: Option<Arc<ProbCut>>,
+        tt: Option<Arc<TranspositionTable>>,
+    ) -> Self {
+        Searcher {
+            interrupt,
+            probcut,
+            tt,
+            deadline: None,
+            ply: 0,
+            stats: SearchStats::default(),
+        }
     }
 
-    let mut valid = if player {
-        get_valid_moves(board.player, board.opponent)
-    } else {
-        get_valid_moves(board.opponent, board.player)
-    };
+    pub fn with_deadline(self, deadline: Instant) -> Self {
+        Searcher {
+            deadline: Some(deadline),
+            ..self
+        }
+    }
 
-    // When there is no valid move.
-    if popcnt64!(valid) == 0 {
-        let valid = if player {
-            get_valid_moves(board.opponent, board.player)
-        } else {
+    ///
+    /// Searches the board and stores the principal variation into `pv`.
+    ///
+    pub fn alpha_beta(
+        &mut self,
+        board: Board,
+        player: bool,
+        depth: u8,
+        alpha: i32,
+        beta: i32,
+        pv: &mut Vec<BoardView>,
+    ) -> Result<i32, ()> {
+        self.ply += 1;
+        let score = self.node(board, player, depth, alpha, beta, pv);
+        self.ply -= 1;
+        score
+    }
+
+    fn node(
+        &mut self,
+        board: Board,
+        player: bool,
+        depth: u8,
+        alpha: i32,
+        beta: i32,
+        pv: &mut Vec<BoardView>,
+    ) -> Result<i32, ()> {
+        if self.interrupt.load(Ordering::Relaxed) {
+            return Err(());
+        }
+        if let Some(deadline) = self.deadline {
+            if self.stats.nodes.is_multiple_of(DEADLINE_INTERVAL) && Instant::now() >= deadline {
+                self.interrupt.store(true, Ordering::Relaxed);
+                return Err(());
+            }
+        }
+
+        pv.clear();
+        self.stats.nodes += 1;
+        self.stats.selective_depth = max(self.stats.selective_depth, self.ply);
+
+        let mut valid = if player {
             get_valid_moves(board.player, board.opponent)
+        } else {
+            get_valid_moves(board.opponent, board.player)
         };
-        // Is the game over?
+
+        // When there is no valid move.
         if popcnt64!(valid) == 0 {
-            let player_num = popcnt64!(board.player);
-            let opponent_num = popcnt64!(board.opponent);
-            if player_num > opponent_num {
-                return Ok(INF);
-            } else if player_num < opponent_num {
-                return Ok(-INF);
+            let valid = if player {
+                get_valid_moves(board.opponent, board.player)
+            } else {
+                get_valid_moves(board.player, board.opponent)
+            };
+            // Is the game over?
+            if popcnt64!(valid) == 0 {
+                let player_num = popcnt64!(board.player);
+                let opponent_num = popcnt64!(board.opponent);
+                if player_num > opponent_num {
+                    return Ok(INF);
+                } else if player_num < opponent_num {
+                    return Ok(-INF);
+                } else {
+                    return Ok(0);
+                }
             } else {
-                return Ok(0);
+                let score = self.alpha_beta(board, !player, depth, alpha, beta, pv)?;
+                pv.insert(0, PASS);
+                return Ok(score);
             }
-        } else {
-            return alpha_beta(interrupt, board, !player, depth, alpha, beta);
         }
-    }
 
-    if depth == 0 {
-        Ok(evaluate(board))
-    } else {
+        if depth == 0 {
+            return Ok(evaluate(board));
+        }
+
+        let mut tt_move = 0;
+        if let Some(tt) = &self.tt {
+            self.stats.tt_probes += 1;
+            if let Some(entry) = tt.probe(board, player) {
+                self.stats.tt_hits += 1;
+                if entry.depth >= depth {
+                    match entry.bound {
+                        Bound::Exact => return Ok(entry.score.clamp(alpha, beta)),
+                        Bound::Lower if entry.score >= beta => return Ok(beta),
+                        Bound::Upper if entry.score <= alpha => return Ok(alpha),
+                        _ => {}
+                    }
+                }
+                tt_move = entry.best.unwrap_or(0) & valid;
+            }
+        }
+
+        if let Some(score) = self.probcut(board, player, depth, alpha, beta)? {
+            return Ok(score);
+        }
+
+        self.stats.interior_nodes += 1;
+
+        let original = (alpha, beta);
         let mut alpha = alpha;
         let mut beta = beta;
+        let mut best = None;
+        let mut child_pv = Vec::new();
+        let mut searched = 0;
 
-        while valid != 0 {
-            let view = 1 << tzcnt64!(valid);
-            valid ^= view;
+        // The move in the table is searched first.
+        valid &= !tt_move;
+        let mut first = tt_move;
+
+        loop {
+            let view = if first != 0 {
+                std::mem::take(&mut first)
+            } else if valid != 0 {
+                let view = 1 << tzcnt64!(valid);
+                valid ^= view;
+                view
+            } else {
+                break;
+            };
 
             let mut board = board;
             if player {
@@ -104,38 +232,213 @@ pub fn alpha_beta(
                 put(view, &mut board.opponent, &mut board.player);
             }
 
-            let score = alpha_beta(interrupt, board, !player, depth - 1, alpha, beta)?;
-            if player {
-                alpha = max(score, alpha);
-                if alpha >= beta {
-                    break;
+            let score = self.alpha_beta(board, !player, depth - 1, alpha, beta, &mut child_pv)?;
+            searched += 1;
+
+            let improved = if player { score > alpha } else { score < beta };
+            if improved {
+                if player {
+                    alpha = score;
+                } else {
+                    beta = score;
+                }
+                best = Some(view);
+                pv.clear();
+                pv.push(view);
+                pv.append(&mut child_pv);
+            }
+            if alpha >= beta {
+                self.stats.cutoffs += 1;
+                if searched == 1 {
+                    self.stats.first_move_cutoffs += 1;
                 }
+                break;
+            }
+        }
+
+        let score = if player { alpha } else { beta };
+
+        if let Some(tt) = &self.tt {
+            let bound = if score <= original.0 {
+                Bound::Upper
+            } else if score >= original.1 {
+                Bound::Lower
             } else {
-                beta = min(score, beta);
-                if alpha >= beta {
-                    break;
+                Bound::Exact
+            };
+            tt.store(
+                board,
+                player,
+                Entry {
+                    score,
+                    depth,
+                    bound,
+                    best,
+                },
+            );
+        }
+
+        Ok(score)
+    }
+
+    ///
+    /// Multi-ProbCut.
+    /// Predicts the result of the deep search from shallow ones and cuts the node off
+    /// when the prediction is out of the window with enough confidence.
+    ///
+    fn probcut(
+        &mut self,
+        board: Board,
+        player: bool,
+        depth: u8,
+        alpha: i32,
+        beta: i32,
+    ) -> Result<Option<i32>, ()> {
+        let probcut = match self.probcut.clone() {
+            Some(probcut) => probcut,
+            None => return Ok(None),
+        };
+
+        let mut pv = Vec::new();
+        for cut in probcut.cuts(depth) {
+            let margin = probcut.threshold * cut.sigma;
+            // The model is fitted for the side to move, while the scores are for `board.player`.
+            let b = if player { cut.b } else { -cut.b };
+
+            if beta < INF {
+                let bound = ((beta as f64 + margin - b) / cut.a).ceil();
+                if bound.abs() < (INF - 1) as f64 {
+                    let bound = bound as i32;
+                    if self.node(board, player, cut.shallow, bound - 1, bound, &mut pv)? >= bound {
+                        return Ok(Some(beta));
+                    }
+                }
+            }
+
+            if alpha > -INF {
+                let bound = ((alpha as f64 - margin - b) / cut.a).floor();
+                if bound.abs() < (INF - 1) as f64 {
+                    let bound = bound as i32;
+                    if self.node(board, player, cut.shallow, bound, bound + 1, &mut pv)? <= bound {
+                        return Ok(Some(alpha));
+                    }
                 }
             }
         }
 
-        if player {
-            Ok(alpha)
+        Ok(None)
+    }
+}
+
+///
+/// A result of the search on a move at the root.
+///
+#[derive(Clone, Debug)]
+pub struct Choice {
+    pub view: BoardView,
+    ///
+    /// The deepest depth whose search has been completed.
+    ///
+    pub depth: u8,
+    pub score: i32,
+    ///
+    /// The principal variation beginning with `view`.
+    ///
+    pub pv: Vec<BoardView>,
+}
+
+///
+/// Searches every move to the fixed depth with the full window.
+///
+pub fn search_moves_at_depth(
+    searcher: &mut Searcher,
+    board: Board,
+    depth: u8,
+) -> Result<Vec<Choice>, ()> {
+    let depth = depth.max(1);
+    let mut valid = get_valid_moves(board.player, board.opponent);
+    let mut choices = Vec::new();
+    let mut pv = Vec::new();
+    while valid != 0 {
+        let view = 1 << tzcnt64!(valid);
+        valid ^= view;
+
+        let mut next = board;
+        put(view, &mut next.player, &mut next.opponent);
+        let score = searcher.alpha_beta(next, false, depth - 1, -INF, INF, &mut pv)?;
+        pv.insert(0, view);
+        choices.push(Choice {
+            view,
+            depth,
+            score,
+            pv: pv.clone(),
+        });
+    }
+    Ok(choices)
+}
+
+///
+/// Searches every move to the fixed depth and returns the best one.
+///
+pub fn best_move_at_depth(
+    searcher: &mut Searcher,
+    board: Board,
+    depth: u8,
+) -> Result<Option<Choice>, ()> {
+    let choices = search_moves_at_depth(searcher, board, depth)?;
+    // The first one is kept on ties.
+    Ok(choices.into_iter().reduce(|best, choice| {
+        if choice.score > best.score {
+            choice
         } else {
-            Ok(beta)
+            best
         }
-    }
+    }))
 }
 
-pub async fn search_move(interrupt: Arc<AtomicBool>, view: BoardView, board: Board) {
-    let mut depth = 5;
+///
+/// Deepens the search on the move until interrupted and returns the last completed result.
+/// The search resumes from `known` if the move has already been searched.
+/// Every completed result is also appended to `results`.
+///
+pub fn search_move(
+    mut searcher: Searcher,
+    view: BoardView,
+    board: Board,
+    known: Option<Choice>,
+    results: &Mutex<Vec<Choice>>,
+) -> (Option<Choice>, SearchStats) {
+    results.lock().unwrap().extend(known.clone());
+    if let Some(choice) = &known {
+        if choice.score == INF || choice.score == -INF {
+            return (known, searcher.stats);
+        }
+    }
+    let mut depth = known.as_ref().map_or(5, |choice| choice.depth + 1);
 
     let mut board = board;
     put(view, &mut board.player, &mut board.opponent);
 
-    while let Ok(score) = alpha_beta(&interrupt, board, false, depth, i32::MIN, i32::MAX) {
-        CHOICES.lock().unwrap().insert(view, score);
+    let mut choice = known;
+    let mut pv = Vec::new();
+    while let Ok(score) = searcher.alpha_beta(board, false, depth, i32::MIN, i32::MAX, &mut pv) {
+        pv.insert(0, view);
+        write_log!(
+            DEBUG,
+            "{}: depth = {}, score = {}, pv = {}",
+            to_notation(view),
+            depth,
+            score,
+            format_line(&pv)
+        );
 
-        write_log!(DEBUG, "View{}: depth = {}, score = {}", view, depth, score);
+        choice = Some(Choice {
+            view,
+            depth,
+            score,
+            pv: pv.clone(),
+        });
+        results.lock().unwrap().extend(choice.clone());
 
         if score == INF || score == -INF {
             break;
@@ -147,57 +450,417 @@ pub async fn search_move(interrupt: Arc<AtomicBool>, view: BoardView, board: Boa
         }
     }
 
-    write_log!(DEBUG, "View{}: Interrupted", view);
+    write_log!(DEBUG, "{}: Interrupted", to_notation(view));
+    (choice, searcher.stats)
 }
 
-static CHOICES: LazyLock<Mutex<HashMap<BoardView, i32>>> =
-    LazyLock::new(|| Mutex::new(HashMap::new()));
+///
+/// Parameters of the engine kept across searches.
+///
+#[derive(Clone, Default)]
+pub struct SearchOptions {
+    pub probcut: Option<Arc<ProbCut>>,
+    pub tt: Option<Arc<TranspositionTable>>,
+    ///
+    /// A flag set from another thread to abort the search. It is never cleared by the search.
+    ///
+    pub abort: Option<Arc<AtomicBool>>,
+}
 
-pub async fn select_best_move(board: Board, duration: Duration) -> Option<BoardView> {
-    let valid = get_valid_moves(board.player, board.opponent);
-    let count = popcnt64!(valid);
+pub struct SearchResult {
+    pub best: Option<BoardView>,
+    ///
+    /// The score of the best move for the side to move, if the engine searched it.
+    ///
+    pub score: Option<i32>,
+    pub stats: SearchStats,
+}
 
-    if count == 0 {
-        None
-    } else if count == 1 {
-        Some(valid)
-    } else {
-        {
-            // Clear previous results.
-            CHOICES.lock().unwrap().clear();
-        }
+///
+/// The interval at which `search_moves` checks the progress of the search.
+///
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
 
-        let interrupt = Arc::new(AtomicBool::new(false));
+///
+/// Searches every move in parallel within the limit and returns the results of the completed ones.
+/// `known` holds the results already obtained on this board, e.g. by pondering.
+///
+pub fn search_moves(
+    board: Board,
+    limit: TimeLimit,
+    options: &SearchOptions,
+    known: &[Choice],
+) -> (Vec<Choice>, SearchStats) {
+    let start = Instant::now();
+    let valid = get_valid_moves(board.player, board.opponent);
+    let count = popcnt64!(valid);
+    let interrupt = Arc::new(AtomicBool::new(false));
 
+    let mut choices = Vec::new();
+    let mut stats = SearchStats::default();
+    let results: Vec<Mutex<Vec<Choice>>> = (0..count).map(|_| Mutex::new(Vec::new())).collect();
+    thread::scope(|scope| {
         let mut tasks = Vec::new();
         let mut counter = valid;
-        while counter != 0 {
+        for slot in &results {
             let view = 1 << tzcnt64!(counter);
             counter ^= view;
 
-            tasks.push(tokio::spawn(search_move(interrupt.clone(), view, board)));
+            let searcher = Searcher::new(
+                interrupt.clone(),
+                options.probcut.clone(),
+                options.tt.clone(),
+            )
+            .with_deadline(start + limit.hard);
+            let known = known.iter().find(|choice| choice.view == view).cloned();
+            tasks.push(scope.spawn(move || search_move(searcher, view, board, known, slot)));
         }
 
         // Waiting for the search.
-        sleep(duration);
+        let mut monitor = SearchMonitor::new(limit);
+        loop {
+            let elapsed = start.elapsed();
+            let progress: Vec<Vec<Choice>> = results
+                .iter()
+                .map(|slot| slot.lock().unwrap().clone())
+                .collect();
+            let aborted = options
+                .abort
+                .as_ref()
+                .is_some_and(|abort| abort.load(Ordering::Relaxed));
+            if aborted
+                || tasks.iter().all(|task| task.is_finished())
+                || monitor.should_stop(elapsed, &progress)
+            {
+                break;
+            }
+            sleep(POLL_INTERVAL.min(monitor.stop_at().saturating_sub(elapsed)));
+        }
 
         write_log!(DEBUG, "Flipping the interrupt flag.");
         interrupt.store(true, Ordering::Relaxed);
 
         for task in tasks {
-            let _ = task.await;
+            if let Ok((choice, task_stats)) = task.join() {
+                stats += task_stats;
+                choices.extend(choice);
+            }
         }
-        write_log!(DEBUG, "The search was interrupted.");
+    });
+    write_log!(DEBUG, "The search was interrupted.");
 
-        match CHOICES
-            .lock()
-            .unwrap()
-            .clone()
-            .into_iter()
-            .max_by_key(|(_, v)| *v)
-        {
-            Some((view, _)) => Some(view),
-            None => Some(1 << tzcnt64!(valid)),
+    stats.depth = if choices.len() == count as usize {
+        choices.iter().map(|choice| choice.depth).min().unwrap_or(0)
+    } else {
+        0
+    };
+    stats.time = start.elapsed();
+    (choices, stats)
+}
+
+///
+/// Searches every move in parallel within the limit.
+/// `known` holds the results already obtained on this board, e.g. by pondering.
+///
+pub fn select_best_move(
+    board: Board,
+    limit: TimeLimit,
+    options: &SearchOptions,
+    known: &[Choice],
+) -> SearchResult {
+    let valid = get_valid_moves(board.player, board.opponent);
+    let count = popcnt64!(valid);
+
+    if count == 0 {
+        SearchResult {
+            best: None,
+            score: None,
+            stats: SearchStats::default(),
+        }
+    } else if count == 1 {
+        SearchResult {
+            best: Some(valid),
+            score: None,
+            stats: SearchStats::default(),
+        }
+    } else {
+        let (choices, stats) = search_moves(board, limit, options, known);
+        write_log!(DEBUG, "Stats: {}", stats);
+
+        let (best, score) = match choices.into_iter().max_by_key(|choice| choice.score) {
+            Some(choice) => {
+                write_log!(
+                    LOG,
+                    "Expected line: {} (depth = {}, score = {})",
+                    format_line(&choice.pv),
+                    choice.depth,
+                    choice.score
+                );
+                (choice.view, Some(choice.score))
+            }
+            None => (1 << tzcnt64!(valid), None),
+        };
+
+        SearchResult {
+            best: Some(best),
+            score,
+            stats,
+        }
+    }
+}
+
+///
+/// An engine playing games.
+/// Boards are always from the viewpoint of the agent.
+///
+pub trait Agent: Send {
+    fn new_game(&mut self, _me: &Color) {}
+
+    ///
+    /// Chooses a move within the limit. `None` means a pass.
+    ///
+    fn choose_move(&mut self, board: Board, limit: TimeLimit) -> SearchResult;
+
+    ///
+    /// Tells the move of the opponent played on the board. `PASS` stands for a pass.
+    ///
+    fn observe(&mut self, _board: Board, _view: BoardView) {}
+
+    ///
+    /// Starts thinking on the opponent's time. The board is the one after the move of the agent.
+    ///
+    fn ponder(&mut self, _board: Board) {}
+
+    ///
+    /// Stops thinking on the opponent's time.
+    ///
+    fn stop_pondering(&mut self) {}
+
+    fn game_end(&mut self, _result: &GameResult) {}
+
+    ///
+    /// Returns the flag aborting `choose_move` from another thread, if the agent supports it.
+    ///
+    fn abort_handle(&self) -> Option<Arc<AtomicBool>> {
+        None
+    }
+}
+
+///
+/// The iterative deepening alpha-beta search.
+///
+pub struct AlphaBetaAgent {
+    options: SearchOptions,
+    ponder: Option<Ponder>,
+    ///
+    /// Whether the agent thinks on the opponent's time.
+    ///
+    pondering: bool,
+    pondered: HashMap<Board, Vec<Choice>>,
+}
+
+impl AlphaBetaAgent {
+    pub fn new(options: SearchOptions, pondering: bool) -> Self {
+        AlphaBetaAgent {
+            options,
+            ponder: None,
+            pondering,
+            pondered: HashMap::new(),
+        }
+    }
+}
+
+impl Agent for AlphaBetaAgent {
+    fn new_game(&mut self, _me: &Color) {
+        self.stop_pondering();
+        self.pondered.clear();
+        if let Some(tt) = &self.options.tt {
+            tt.clear();
         }
     }
+
+    fn choose_move(&mut self, board: Board, limit: TimeLimit) -> SearchResult {
+        self.stop_pondering();
+        let known = self.pondered.remove(&board).unwrap_or_default();
+        self.pondered.clear();
+        if !known.is_empty() {
+            write_log!(
+                DEBUG,
+                "Reusing the pondered results up to depth {}.",
+                known.iter().map(|choice| choice.depth).min().unwrap_or(0)
+            );
+        }
+        select_best_move(board, limit, &self.options, &known)
+    }
+
+    fn ponder(&mut self, board: Board) {
+        if self.pondering {
+            self.stop_pondering();
+            self.ponder = Some(Ponder::start(board, &self.options));
+        }
+    }
+
+    fn stop_pondering(&mut self) {
+        if let Some(ponder) = self.ponder.take() {
+            self.pondered = ponder.stop();
+        }
+    }
+
+    fn abort_handle(&self) -> Option<Arc<AtomicBool>> {
+        self.options.abort.clone()
+    }
+}
+
+///
+/// Creates the agent specified by the command line.
+///
+pub fn create_agent(args: &Args) -> Result<Box<dyn Agent>, Error> {
+    let options = SearchOptions {
+        probcut: load_probcut(args)?,
+        tt: Some(Arc::new(TranspositionTable::new(tt::DEFAULT_BITS))),
+        abort: Some(Arc::new(AtomicBool::new(false))),
+    };
+
+    let agent: Box<dyn Agent> = match args.engine {
+        Engine::AlphaBeta => Box::new(AlphaBetaAgent::new(options, args.ponder)),
+        Engine::Mcts => Box::new(MctsAgent::new(
+            MctsOptions {
+                exploration: args.mcts_exploration,
+                heuristic: args.mcts_heuristic,
+            },
+            args.seed,
+        )),
+        Engine::Random => Box::new(RandomAgent::new(args.seed)),
+        Engine::Greedy => Box::new(GreedyAgent),
+        Engine::FixedDepth => Box::new(FixedDepthAgent {
+            depth: args.depth,
+            options,
+        }),
+    };
+    Ok(agent)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        board::{get_pos, new_board},
+        util::Rng,
+    };
+
+    #[test]
+    fn test_tt_keeps_scores() {
+        let mut rng = Rng::new(1);
+        let mut board = new_board(&Color::Black);
+        for _ in 0..12 {
+            let valid = get_valid_moves(board.player, board.opponent);
+            put(rng.pick(valid), &mut board.player, &mut board.opponent);
+            board = Board {
+                player: board.opponent,
+                opponent: board.player,
+            };
+
+            // A fresh table per board, shared only by the deepening iterations on it.
+            let tt = Arc::new(TranspositionTable::new(12));
+            let interrupt = Arc::new(AtomicBool::new(false));
+            let mut plain = Searcher::new(interrupt.clone(), None, None);
+            let mut cached = Searcher::new(interrupt, None, Some(tt));
+            let mut pv = Vec::new();
+            for depth in 1..=4 {
+                assert_eq!(
+                    plain.alpha_beta(board, true, depth, -INF, INF, &mut pv),
+                    cached.alpha_beta(board, true, depth, -INF, INF, &mut pv)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_probcut() {
+        let probcut = Some(Arc::new(ProbCut::default()));
+        let interrupt = Arc::new(AtomicBool::new(false));
+        let positions = crate::bench::bench_positions(16, 2);
+        let (mut kept, mut plain_nodes, mut cut_nodes) = (0, 0, 0);
+        for &board in &positions {
+            let mut plain = Searcher::new(interrupt.clone(), None, None);
+            let mut cut = Searcher::new(interrupt.clone(), probcut.clone(), None);
+            let expected = best_move_at_depth(&mut plain, board, 6).unwrap().unwrap();
+            let actual = best_move_at_depth(&mut cut, board, 6).unwrap().unwrap();
+            kept += (actual.view == expected.view) as usize;
+            plain_nodes += plain.stats.nodes;
+            cut_nodes += cut.stats.nodes;
+
+            // The cuts are the same for both sides: with the colors swapped and the other side
+            // to move, the windows and the results are negated.
+            let swapped = Board {
+                player: board.opponent,
+                opponent: board.player,
+            };
+            for beta in (-400..=400).step_by(4) {
+                assert_eq!(
+                    cut.probcut(board, true, 6, beta - 1, beta),
+                    cut.probcut(swapped, false, 6, -beta, 1 - beta)
+                        .map(|score| score.map(|score| -score))
+                );
+            }
+        }
+        assert!(kept * 8 >= positions.len() * 7, "{} kept", kept);
+        assert!(cut_nodes < plain_nodes, "{} >= {}", cut_nodes, plain_nodes);
+    }
+
+    #[test]
+    fn test_create_agent() {
+        use clap::Parser;
+
+        let positions = crate::bench::bench_positions(8, 0);
+        let limit = TimeLimit::fixed(Duration::ZERO);
+        let moves = |agent: &mut dyn Agent| {
+            positions
+                .iter()
+                .map(|&board| agent.choose_move(board, limit).best)
+                .collect::<Vec<_>>()
+        };
+        let create = |engine: &str| {
+            create_agent(&Args::parse_from([
+                "rinee", "--engine", engine, "--seed", "3", "--depth", "2", "--no-mpc",
+            ]))
+            .unwrap()
+        };
+
+        assert_eq!(moves(create("greedy").as_mut()), moves(&mut GreedyAgent));
+        assert_eq!(
+            moves(create("random").as_mut()),
+            moves(&mut RandomAgent::new(3))
+        );
+        let mut fixed = FixedDepthAgent {
+            depth: 2,
+            options: SearchOptions::default(),
+        };
+        assert_eq!(moves(create("fixed-depth").as_mut()), moves(&mut fixed));
+        assert!(create("alpha-beta").abort_handle().is_some());
+        assert!(create("mcts").abort_handle().is_some());
+        assert!(create("random").abort_handle().is_none());
+    }
+
+    #[test]
+    fn test_pondered_reuse() {
+        let mut agent = AlphaBetaAgent::new(SearchOptions::default(), true);
+        agent.new_game(&Color::Black);
+
+        // After C4, the opponent replies C3 while the agent ponders.
+        let mut board = new_board(&Color::Black);
+        put(get_pos(2, 3), &mut board.player, &mut board.opponent);
+        agent.ponder(board);
+        thread::sleep(Duration::from_millis(500));
+        agent.observe(board, get_pos(2, 2));
+        put(get_pos(2, 2), &mut board.opponent, &mut board.player);
+
+        // Without time to search, only the pondered results give a depth.
+        let result = agent.choose_move(board, TimeLimit::fixed(Duration::ZERO));
+        assert!(result.stats.depth >= 5, "depth = {}", result.stats.depth);
+        assert_ne!(
+            result.best.unwrap() & get_valid_moves(board.player, board.opponent),
+            0
+        );
+    }
 }
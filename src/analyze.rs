@@ -0,0 +1,144 @@
+//
+// Offline analysis of a position listing the scores of every legal move.
+//
+
+use std::{
+    sync::{atomic::AtomicBool, Arc},
+    time::Duration,
+};
+
+use crate::{
+    agent::{search_moves, search_moves_at_depth, Choice, SearchOptions, Searcher, INF},
+    board::{format_line, get_valid_moves, parse_position, to_notation, Board},
+    book::replay_to_move,
+    clock::TimeLimit,
+    play::render,
+    popcnt64,
+    proto::{Color, Error},
+};
+
+pub enum Limit {
+    Time(Duration),
+    Depth(u8),
+}
+
+///
+/// Parses either a position like `parse_position` or a transcript like `F5D6C3`.
+/// The side to move passes if it has no legal move.
+///
+pub fn parse_input(input: &str) -> Option<(Board, Color)> {
+    let (board, color) = parse_position(input).or_else(|| replay_to_move(input.trim()))?;
+    if get_valid_moves(board.player, board.opponent) == 0
+        && get_valid_moves(board.opponent, board.player) != 0
+    {
+        let opponent = match color {
+            Color::Black => Color::White,
+            Color::White => Color::Black,
+        };
+        return Some((
+            Board {
+                player: board.opponent,
+                opponent: board.player,
+            },
+            opponent,
+        ));
+    }
+    Some((board, color))
+}
+
+///
+/// Searches every legal move and returns the results sorted best-first.
+/// The depths count the plies from the position.
+///
+pub fn analyze(board: Board, limit: &Limit, options: &SearchOptions) -> Vec<Choice> {
+    let mut choices = match *limit {
+        Limit::Time(duration) => {
+            let (choices, _) = search_moves(board, TimeLimit::fixed(duration), options, &[]);
+            // The depths of the timed search count the plies after the move.
+            choices
+                .into_iter()
+                .map(|choice| Choice {
+                    depth: choice.depth + 1,
+                    ..choice
+                })
+                .collect()
+        }
+        Limit::Depth(depth) => {
+            let mut searcher = Searcher::new(
+                Arc::new(AtomicBool::new(false)),
+                options.probcut.clone(),
+                options.tt.clone(),
+            );
+            search_moves_at_depth(&mut searcher, board, depth)
+                .expect("the search is never interrupted")
+        }
+    };
+    choices.sort_by_key(|choice| (-(choice.score as i64), choice.view));
+    choices
+}
+
+fn format_score(score: i32) -> String {
+    if score == INF {
+        String::from("win")
+    } else if score == -INF {
+        String::from("loss")
+    } else {
+        format!("{:+}", score)
+    }
+}
+
+pub fn run(input: &str, limit: &Limit, options: &SearchOptions) -> Result<(), Error> {
+    let (board, color) =
+        parse_input(input).ok_or_else(|| Error::ParserWithMessage(input.to_string()))?;
+    println!("{}", render(board, color, None));
+
+    let valid = get_valid_moves(board.player, board.opponent);
+    if valid == 0 {
+        println!("The game is over.");
+        return Ok(());
+    }
+
+    let choices = analyze(board, limit, options);
+    println!("{:<6}{:>8}{:>7}  PV", "Move", "Score", "Depth");
+    for choice in &choices {
+        println!(
+            "{:<6}{:>8}{:>7}  {}",
+            to_notation(choice.view),
+            format_score(choice.score),
+            choice.depth,
+            format_line(&choice.pv)
+        );
+    }
+    let missing = popcnt64!(valid) as usize - choices.len();
+    if missing > 0 {
+        println!("{} moves were not searched in time.", missing);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::board::from_notation;
+
+    #[test]
+    fn test_analyze() {
+        let (board, color) = parse_input("F5D6").unwrap();
+        assert_eq!(color, Color::Black);
+
+        let choices = analyze(board, &Limit::Depth(3), &SearchOptions::default());
+        assert_eq!(
+            choices.len(),
+            popcnt64!(get_valid_moves(board.player, board.opponent)) as usize
+        );
+        assert!(choices.windows(2).all(|w| w[0].score >= w[1].score));
+        assert!(choices.iter().all(|choice| choice.pv[0] == choice.view));
+        assert!(choices
+            .iter()
+            .any(|choice| choice.view == from_notation("C3").unwrap()));
+
+        // The position format gives the same board.
+        let position = "---------------------------OX------XO--------------------------- X";
+        assert_eq!(parse_input(position), parse_input(""));
+    }
+}
@@ -0,0 +1,400 @@
+//
+// Engine-vs-engine matches in the process.
+// Each opening is played twice with the colors swapped, and the games run in parallel.
+//
+
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    agent::{create_agent, Agent, Searcher, VALUE_PER_DISC},
+    board::{canonicalize, get_valid_moves, new_board, put, Board, BoardView, PASS},
+    clock::TimeLimit,
+    popcnt64,
+    proto::{Color, Error, GameResult},
+    util::Rng,
+    write_log, Args,
+};
+
+///
+/// Openings whose search score is beyond this are thrown away as unbalanced.
+///
+const BALANCE: i32 = 4 * VALUE_PER_DISC;
+
+///
+/// The depth of the search judging the balance of openings.
+///
+const BALANCE_DEPTH: u8 = 4;
+
+pub struct ArenaOptions {
+    ///
+    /// The number of openings, each of which is played twice.
+    ///
+    pub openings: usize,
+    ///
+    /// The number of random moves in the openings.
+    ///
+    pub opening_plies: usize,
+    pub time: Duration,
+    pub jobs: usize,
+    pub seed: u64,
+    ///
+    /// The Elo differences of the null and alternative hypotheses of the SPRT.
+    ///
+    pub sprt: Option<(f64, f64)>,
+}
+
+///
+/// The results from the viewpoint of the first engine.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Report {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    ///
+    /// The sum of the disc differentials.
+    ///
+    pub discs: i32,
+}
+
+impl Report {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn add(&mut self, diff: i32) {
+        match diff.signum() {
+            1 => self.wins += 1,
+            0 => self.draws += 1,
+            _ => self.losses += 1,
+        }
+        self.discs += diff;
+    }
+
+    ///
+    /// The average score and its variance per game.
+    ///
+    fn score(&self) -> (f64, f64) {
+        let n = self.games() as f64;
+        let p = (self.wins as f64 + self.draws as f64 / 2.0) / n;
+        let variance = (self.wins as f64 * (1.0 - p).powi(2)
+            + self.draws as f64 * (0.5 - p).powi(2)
+            + self.losses as f64 * p.powi(2))
+            / n;
+        (p, variance)
+    }
+
+    ///
+    /// The Elo difference and the half width of its 95% confidence interval.
+    ///
+    pub fn elo(&self) -> (f64, f64) {
+        let (p, variance) = self.score();
+        let margin = 1.96 * (variance / self.games() as f64).sqrt();
+        let elo = elo_of(p);
+        let low = elo_of(p - margin);
+        let high = elo_of(p + margin);
+        (elo, (high - low) / 2.0)
+    }
+
+    ///
+    /// The log-likelihood ratio of the SPRT by the normal approximation.
+    ///
+    pub fn llr(&self, elo0: f64, elo1: f64) -> f64 {
+        let (p, variance) = self.score();
+        if variance == 0.0 {
+            return 0.0;
+        }
+        let (p0, p1) = (score_of(elo0), score_of(elo1));
+        self.games() as f64 * (p1 - p0) * (2.0 * p - p0 - p1) / (2.0 * variance)
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (elo, margin) = self.elo();
+        write!(
+            f,
+            "games = {}, W/D/L = {}/{}/{}, discs = {:+.2}, elo = {:+.1} +/- {:.1}",
+            self.games(),
+            self.wins,
+            self.draws,
+            self.losses,
+            self.discs as f64 / self.games().max(1) as f64,
+            elo,
+            margin
+        )
+    }
+}
+
+fn elo_of(score: f64) -> f64 {
+    let score = score.clamp(1e-6, 1.0 - 1e-6);
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+fn score_of(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+///
+/// Bounds of the SPRT with both error rates of 5%.
+///
+fn sprt_bounds() -> (f64, f64) {
+    let (alpha, beta) = (0.05f64, 0.05f64);
+    ((beta / (1.0 - alpha)).ln(), ((1.0 - beta) / alpha).ln())
+}
+
+///
+/// Generates distinct openings of random moves which the shallow search considers balanced.
+///
+pub fn openings(count: usize, plies: usize, seed: u64) -> Vec<Vec<BoardView>> {
+    let mut rng = Rng::new(seed);
+    let mut searcher = Searcher::new(Arc::new(AtomicBool::new(false)), None, None);
+    let mut pv = Vec::new();
+    let mut seen = HashSet::new();
+    let mut openings = Vec::new();
+
+    for _ in 0..count * 100 {
+        if openings.len() == count {
+            break;
+        }
+        let mut board = new_board(&Color::Black);
+        let mut moves = Vec::new();
+        while moves.len() < plies {
+            let valid = get_valid_moves(board.player, board.opponent);
+            if valid == 0 {
+                break;
+            }
+            let view = rng.pick(valid);
+            put(view, &mut board.player, &mut board.opponent);
+            board = Board {
+                player: board.opponent,
+                opponent: board.player,
+            };
+            moves.push(view);
+        }
+        if moves.len() < plies || !seen.insert(canonicalize(board).0) {
+            continue;
+        }
+
+        let score = searcher
+            .alpha_beta(
+                board,
+                true,
+                BALANCE_DEPTH,
+                -BALANCE - 1,
+                BALANCE + 1,
+                &mut pv,
+            )
+            .expect("the search is never interrupted");
+        if score.abs() <= BALANCE {
+            openings.push(moves);
+        }
+    }
+    openings
+}
+
+///
+/// Plays a game from the opening and returns the disc differential for `agents[0]`.
+/// An engine passing or playing an illegal move while it has a legal one loses all the discs.
+///
+pub fn play_game(
+    agents: &mut [Box<dyn Agent>; 2],
+    black: usize,
+    opening: &[BoardView],
+    time: Duration,
+) -> i32 {
+    agents[black].new_game(&Color::Black);
+    agents[1 - black].new_game(&Color::White);
+
+    // The board from the viewpoint of the side to move.
+    let mut board = new_board(&Color::Black);
+    let mut turn = black;
+    for &view in opening {
+        put(view, &mut board.player, &mut board.opponent);
+        board = Board {
+            player: board.opponent,
+            opponent: board.player,
+        };
+        turn = 1 - turn;
+    }
+
+    let diff = loop {
+        let valid = get_valid_moves(board.player, board.opponent);
+        let view = if valid == 0 {
+            if get_valid_moves(board.opponent, board.player) == 0 {
+                let diff = popcnt64!(board.player) as i32 - popcnt64!(board.opponent) as i32;
+                break if turn == 0 { diff } else { -diff };
+            }
+            PASS
+        } else {
+            match agents[turn].choose_move(board, TimeLimit::fixed(time)).best {
+                Some(view) if view & valid != 0 => view,
+                _ => break if turn == 0 { -64 } else { 64 },
+            }
+        };
+
+        agents[1 - turn].observe(
+            Board {
+                player: board.opponent,
+                opponent: board.player,
+            },
+            view,
+        );
+        if view != PASS {
+            put(view, &mut board.player, &mut board.opponent);
+        }
+        agents[turn].ponder(board);
+        board = Board {
+            player: board.opponent,
+            opponent: board.player,
+        };
+        turn = 1 - turn;
+    };
+
+    for (i, agent) in agents.iter_mut().enumerate() {
+        agent.stop_pondering();
+        let diff = if i == 0 { diff } else { -diff };
+        agent.game_end(&match diff.signum() {
+            1 => GameResult::Win,
+            0 => GameResult::Tie,
+            _ => GameResult::Lose,
+        });
+    }
+    diff
+}
+
+///
+/// Returns the number of the games played in parallel for `--jobs`.
+/// Every search runs a thread per legal move and every agent has its own transposition table,
+/// so the games are capped at the number of the CPUs and default to a quarter of them.
+/// More games would make the engines compete for the CPUs and blur their strength under the
+/// time limit.
+///
+pub fn jobs(requested: Option<usize>) -> usize {
+    let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+    match requested {
+        Some(jobs) if jobs > cpus => {
+            write_log!(WARN, "Playing {} games in parallel, one per CPU.", cpus);
+            cpus
+        }
+        Some(jobs) => jobs.max(1),
+        None => (cpus / 4).max(1),
+    }
+}
+
+///
+/// Plays the match between the engines specified by the arguments.
+///
+pub fn run(first: &Args, second: &Args, options: &ArenaOptions) -> Result<Report, Error> {
+    let openings = openings(options.openings, options.opening_plies, options.seed);
+    println!("Playing {} openings from both colors.", openings.len());
+
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+    let mut report = Report::default();
+
+    let workers = (0..options.jobs.max(1))
+        .map(|_| Ok([create_agent(first)?, create_agent(second)?]))
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    thread::scope(|scope| {
+        for mut agents in workers {
+            let (openings, next, stop, sender) = (&openings, &next, &stop, sender.clone());
+            scope.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let game = next.fetch_add(1, Ordering::Relaxed);
+                    if game >= openings.len() * 2 {
+                        break;
+                    }
+                    let diff = play_game(&mut agents, game % 2, &openings[game / 2], options.time);
+                    if sender.send(diff).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        for diff in receiver {
+            report.add(diff);
+            println!("{}", report);
+
+            if let Some((elo0, elo1)) = options.sprt {
+                let llr = report.llr(elo0, elo1);
+                let (lower, upper) = sprt_bounds();
+                if llr <= lower || llr >= upper {
+                    println!(
+                        "SPRT: LLR = {:.2} ({:.2}, {:.2}), H{} is accepted.",
+                        llr,
+                        lower,
+                        upper,
+                        if llr >= upper { 1 } else { 0 }
+                    );
+                    stop.store(true, Ordering::Relaxed);
+                    break;
+                }
+            }
+        }
+    });
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::baseline::{GreedyAgent, RandomAgent};
+
+    #[test]
+    fn test_elo() {
+        let report = Report {
+            wins: 30,
+            draws: 0,
+            losses: 10,
+            discs: 0,
+        };
+        let (elo, margin) = report.elo();
+        // A score of 75% is about 191 Elo.
+        assert!((elo - 190.8).abs() < 0.1);
+        assert!(margin > 0.0 && margin < elo);
+
+        let even = Report {
+            wins: 10,
+            draws: 0,
+            losses: 10,
+            discs: 0,
+        };
+        assert!(even.elo().0.abs() < 1e-9);
+        assert!(report.llr(0.0, 20.0) > 0.0);
+        assert!(even.llr(0.0, 20.0) < 0.0);
+    }
+
+    #[test]
+    fn test_jobs() {
+        let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+        assert_eq!(jobs(Some(usize::MAX)), cpus);
+        assert_eq!(jobs(Some(0)), 1);
+        assert!((1..=cpus).contains(&jobs(None)));
+    }
+
+    #[test]
+    fn test_play_game() {
+        let openings = openings(4, 4, 0);
+        assert_eq!(openings.len(), 4);
+
+        let mut agents: [Box<dyn Agent>; 2] =
+            [Box::new(GreedyAgent), Box::new(RandomAgent::new(0))];
+        for (i, opening) in openings.iter().enumerate() {
+            let diff = play_game(&mut agents, i % 2, opening, Duration::ZERO);
+            assert!((-64..=64).contains(&diff));
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//
+// Weak agents for regression testing.
+//
+
+use std::{
+    sync::{atomic::AtomicBool, Arc},
+    time::Instant,
+};
+
+use crate::{
+    agent::{best_move_at_depth, Agent, SearchOptions, SearchResult, Searcher},
+    board::{get_valid_moves, put, Board},
+    clock::TimeLimit,
+    popcnt64,
+    proto::Color,
+    stats::SearchStats,
+    tzcnt64,
+    util::Rng,
+};
+
+///
+/// Plays a random legal move.
+///
+pub struct RandomAgent {
+    rng: Rng,
+}
+
+impl RandomAgent {
+    pub fn new(seed: u64) -> Self {
+        RandomAgent {
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl Agent for RandomAgent {
+    fn choose_move(&mut self, board: Board, _limit: TimeLimit) -> SearchResult {
+        let valid = get_valid_moves(board.player, board.opponent);
+        SearchResult {
+            best: if valid == 0 {
+                None
+            } else {
+                Some(self.rng.pick(valid))
+            },
+            score: None,
+            stats: SearchStats::default(),
+        }
+    }
+}
+
+///
+/// Plays the move flipping the most discs.
+///
+pub struct GreedyAgent;
+
+impl Agent for GreedyAgent {
+    fn choose_move(&mut self, board: Board, _limit: TimeLimit) -> SearchResult {
+        let mut valid = get_valid_moves(board.player, board.opponent);
+        let mut best = None;
+        let mut best_flips = 0;
+        while valid != 0 {
+            let view = 1 << tzcnt64!(valid);
+            valid ^= view;
+
+            let mut next = board;
+            put(view, &mut next.player, &mut next.opponent);
+            let flips = popcnt64!(board.opponent & !next.opponent);
+            if flips > best_flips {
+                best = Some(view);
+                best_flips = flips;
+            }
+        }
+        SearchResult {
+            best,
+            score: None,
+            stats: SearchStats::default(),
+        }
+    }
+}
+
+///
+/// Searches to the fixed depth regardless of the time.
+///
+pub struct FixedDepthAgent {
+    pub depth: u8,
+    pub options: SearchOptions,
+}
+
+impl Agent for FixedDepthAgent {
+    fn new_game(&mut self, _me: &Color) {
+        if let Some(tt) = &self.options.tt {
+            tt.clear();
+        }
+    }
+
+    fn choose_move(&mut self, board: Board, _limit: TimeLimit) -> SearchResult {
+        let start = Instant::now();
+        let mut searcher = Searcher::new(
+            Arc::new(AtomicBool::new(false)),
+            self.options.probcut.clone(),
+            self.options.tt.clone(),
+        );
+        let choice = best_move_at_depth(&mut searcher, board, self.depth)
+            .expect("the search is never interrupted");
+
+        let mut stats = searcher.stats;
+        stats.depth = self.depth;
+        stats.time = start.elapsed();
+        SearchResult {
+            best: choice.as_ref().map(|choice| choice.view),
+            score: choice.map(|choice| choice.score),
+            stats,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::bench::bench_positions;
+
+    fn limit() -> TimeLimit {
+        TimeLimit::fixed(Duration::ZERO)
+    }
+
+    fn flips(board: Board, view: u64) -> i32 {
+        let mut next = board;
+        put(view, &mut next.player, &mut next.opponent);
+        popcnt64!(board.opponent & !next.opponent)
+    }
+
+    #[test]
+    fn test_greedy() {
+        for board in bench_positions(16, 0) {
+            let mut valid = get_valid_moves(board.player, board.opponent);
+            let mut most = 0;
+            while valid != 0 {
+                let view = 1 << tzcnt64!(valid);
+                valid ^= view;
+                most = most.max(flips(board, view));
+            }
+            let best = GreedyAgent.choose_move(board, limit()).best.unwrap();
+            assert_eq!(flips(board, best), most);
+        }
+    }
+
+    #[test]
+    fn test_random() {
+        let positions = bench_positions(16, 0);
+        let play = |seed| {
+            let mut agent = RandomAgent::new(seed);
+            positions
+                .iter()
+                .map(|&board| agent.choose_move(board, limit()).best.unwrap())
+                .collect::<Vec<_>>()
+        };
+        let moves = play(1);
+        for (board, view) in positions.iter().zip(&moves) {
+            assert_ne!(view & get_valid_moves(board.player, board.opponent), 0);
+        }
+        assert_eq!(moves, play(1));
+        assert_ne!(moves, play(2));
+    }
+
+    #[test]
+    fn test_fixed_depth() {
+        let mut agent = FixedDepthAgent {
+            depth: 3,
+            options: SearchOptions::default(),
+        };
+        for board in bench_positions(8, 0) {
+            let mut searcher = Searcher::new(Arc::new(AtomicBool::new(false)), None, None);
+            let choice = best_move_at_depth(&mut searcher, board, 3)
+                .unwrap()
+                .unwrap();
+            let result = agent.choose_move(board, limit());
+            assert_eq!(result.best, Some(choice.view));
+            assert_eq!(result.score, Some(choice.score));
+        }
+    }
+}
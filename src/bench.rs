@@ -0,0 +1,472 @@
+//
+// Benchmarks measuring the speed and the correctness of the engine.
+//
+
+use std::{
+    collections::HashMap,
+    hint::black_box,
+    sync::{atomic::AtomicBool, Arc},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    agent::{best_move_at_depth, evaluate, Searcher},
+    board::{
+        from_notation, get_confirm_stone, get_valid_moves, new_board, parse_position, put,
+        to_notation, Board, BoardView,
+    },
+    endgame::Solver,
+    popcnt64,
+    proto::{Color, Error},
+    tzcnt64,
+    util::Rng,
+};
+
+pub static DEFAULT_BASELINE_FILE: &str = "data/bench_baseline.txt";
+
+///
+/// The number of positions measured by `rinee bench perf`.
+///
+pub const PERF_POSITIONS: usize = 64;
+
+///
+/// Results slower than the baseline by this ratio are reported as regressions.
+///
+const REGRESSION: f64 = 1.1;
+
+///
+/// The FFO endgame test positions from #40.
+///
+static FFO: &str = include_str!("../data/ffo.txt");
+
+///
+/// The size of the table of the solver in bits.
+///
+const SOLVER_BITS: u32 = 22;
+
+///
+/// A position with the best moves and the exact score known.
+///
+pub struct EndgameTest {
+    pub number: u32,
+    ///
+    /// The board from the viewpoint of the side to move.
+    ///
+    pub board: Board,
+    pub best: Vec<BoardView>,
+    pub score: i32,
+}
+
+///
+/// Parses the lines like `40 <64 squares> X A2 +38`, where the best moves are separated by commas.
+/// Empty lines and the ones starting with `#` are skipped.
+///
+pub fn parse_tests(text: &str) -> Result<Vec<EndgameTest>, Error> {
+    let mut tests = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = || Error::ParserWithMessage(line.to_string());
+        let test = match line.split_whitespace().collect::<Vec<_>>()[..] {
+            [number, squares, side, best, score] => {
+                let (board, _) =
+                    parse_position(&format!("{} {}", squares, side)).ok_or_else(error)?;
+                EndgameTest {
+                    number: number.parse().map_err(|_| error())?,
+                    board,
+                    best: best
+                        .split(',')
+                        .map(from_notation)
+                        .collect::<Option<_>>()
+                        .ok_or_else(error)?,
+                    score: score.parse().map_err(|_| error())?,
+                }
+            }
+            _ => return Err(error()),
+        };
+        tests.push(test);
+    }
+    Ok(tests)
+}
+
+pub fn ffo_tests() -> Vec<EndgameTest> {
+    parse_tests(FFO).expect("the bundled positions are valid")
+}
+
+///
+/// Solves the positions whose numbers are within `range` and reports the results.
+/// Returns the numbers of the positions solved correctly and of the ones tested.
+///
+pub fn run_endgame(tests: &[EndgameTest], range: (u32, u32)) -> (usize, usize) {
+    println!(
+        "{:>3} {:>7} {:>6} {:>9} {:>6} {:>14} {:>10} {:>12}",
+        "#", "empties", "move", "expected", "score", "nodes", "time", "NPS"
+    );
+    let mut correct = 0;
+    let mut total = (0u64, Duration::ZERO, 0usize);
+    for test in tests
+        .iter()
+        .filter(|test| (range.0..=range.1).contains(&test.number))
+    {
+        let mut solver = Solver::new(SOLVER_BITS);
+        let start = Instant::now();
+        let (score, best) = solver.solve(test.board);
+        let time = start.elapsed();
+
+        let ok = score == test.score && best.is_some_and(|best| test.best.contains(&best));
+        if ok {
+            correct += 1;
+        }
+        total = (total.0 + solver.nodes, total.1 + time, total.2 + 1);
+        println!(
+            "{:>3} {:>7} {:>6} {:>9} {:>+6} {:>14} {:>9.2}s {:>12.0} {}",
+            test.number,
+            64 - popcnt64!(test.board.player | test.board.opponent),
+            best.map_or(String::from("-"), to_notation),
+            format!("{}{:+}", to_notation(test.best[0]), test.score),
+            score,
+            solver.nodes,
+            time.as_secs_f64(),
+            solver.nodes as f64 / time.as_secs_f64(),
+            if ok { "" } else { "WRONG" }
+        );
+    }
+    println!(
+        "{}/{} correct, {} nodes in {:.2}s ({:.0} NPS)",
+        correct,
+        total.2,
+        total.0,
+        total.1.as_secs_f64(),
+        total.0 as f64 / total.1.as_secs_f64()
+    );
+    (correct, total.2)
+}
+
+///
+/// Solves the positions of the file, or the bundled ones, whose numbers are within the range,
+/// which covers all of them by default. Fails if any of them is solved wrongly.
+///
+pub fn run_ffo(file: Option<&str>, first: Option<u32>, last: Option<u32>) -> Result<(), Error> {
+    let tests = match file {
+        Some(file) => parse_tests(&std::fs::read_to_string(file)?)?,
+        None => ffo_tests(),
+    };
+    let numbers = tests.iter().map(|test| test.number);
+    let range = (
+        first.unwrap_or_else(|| numbers.clone().min().unwrap_or(0)),
+        last.unwrap_or_else(|| numbers.max().unwrap_or(0)),
+    );
+    match run_endgame(&tests, range) {
+        (correct, tested) if correct < tested => Err(Error::Failed(format!(
+            "{} of {} positions are solved wrongly.",
+            tested - correct,
+            tested
+        ))),
+        _ => Ok(()),
+    }
+}
+
+///
+/// Generates positions of random games, spread from the opening to the endgame.
+///
+pub fn bench_positions(count: usize, seed: u64) -> Vec<Board> {
+    let mut rng = Rng::new(seed);
+    let mut positions = Vec::with_capacity(count);
+    while positions.len() < count {
+        let plies = 4 + positions.len() * 50 / count;
+        let mut board = new_board(&Color::Black);
+        for _ in 0..plies {
+            let valid = get_valid_moves(board.player, board.opponent);
+            if valid == 0 {
+                break;
+            }
+            put(rng.pick(valid), &mut board.player, &mut board.opponent);
+            board = Board {
+                player: board.opponent,
+                opponent: board.player,
+            };
+        }
+        if get_valid_moves(board.player, board.opponent) != 0 {
+            positions.push(board);
+        }
+    }
+    positions
+}
+
+pub struct PerfOptions {
+    ///
+    /// The number of times each function runs over the positions.
+    ///
+    pub rounds: usize,
+    ///
+    /// The depth of the fixed-depth search.
+    ///
+    pub depth: u8,
+}
+
+///
+/// A result of a benchmark. Smaller values are better.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Measurement {
+    pub name: String,
+    pub value: f64,
+    pub unit: String,
+}
+
+///
+/// The number of samples taken by each benchmark, of which the fastest one is reported.
+///
+const SAMPLES: usize = 5;
+
+///
+/// Runs `f` over the positions for the rounds and returns the time per call in nanoseconds.
+/// `f` returns the number of the calls it makes.
+///
+fn time_per_call<F: FnMut(Board) -> u64>(positions: &[Board], rounds: usize, mut f: F) -> f64 {
+    (0..SAMPLES)
+        .map(|_| {
+            let start = Instant::now();
+            let mut calls = 0u64;
+            for _ in 0..rounds.div_ceil(SAMPLES) {
+                for &board in positions {
+                    calls += black_box(f(black_box(board)));
+                }
+            }
+            start.elapsed().as_nanos() as f64 / calls.max(1) as f64
+        })
+        .fold(f64::INFINITY, f64::min)
+}
+
+///
+/// Measures the hot paths of the board, the evaluation and the search.
+///
+pub fn measure(positions: &[Board], options: &PerfOptions) -> Vec<Measurement> {
+    let measurement = |name: &str, value: f64, unit: &str| Measurement {
+        name: name.to_string(),
+        value,
+        unit: unit.to_string(),
+    };
+
+    let mut measurements = vec![
+        measurement(
+            "get_valid_moves",
+            time_per_call(positions, options.rounds, |board| {
+                black_box(get_valid_moves(board.player, board.opponent));
+                1
+            }),
+            "ns",
+        ),
+        measurement(
+            "put",
+            time_per_call(positions, options.rounds, |board| {
+                let mut valid = get_valid_moves(board.player, board.opponent);
+                let count = popcnt64!(valid) as u64;
+                while valid != 0 {
+                    let view = 1 << tzcnt64!(valid);
+                    valid ^= view;
+                    let mut board = board;
+                    put(view, &mut board.player, &mut board.opponent);
+                    black_box(board);
+                }
+                count
+            }),
+            "ns",
+        ),
+        measurement(
+            "get_confirm_stone",
+            time_per_call(positions, options.rounds, |board| {
+                black_box(get_confirm_stone(board.player));
+                1
+            }),
+            "ns",
+        ),
+        measurement(
+            "evaluate",
+            time_per_call(positions, options.rounds, |board| {
+                black_box(evaluate(board));
+                1
+            }),
+            "ns",
+        ),
+    ];
+
+    let mut searcher = Searcher::new(Arc::new(AtomicBool::new(false)), None, None);
+    let start = Instant::now();
+    for &board in positions {
+        black_box(
+            best_move_at_depth(&mut searcher, board, options.depth)
+                .expect("the search is never interrupted"),
+        );
+    }
+    let time = start.elapsed();
+    let nodes = searcher.stats.nodes;
+    measurements.push(measurement(
+        &format!("search_depth_{}", options.depth),
+        time.as_secs_f64() * 1000.0 / positions.len() as f64,
+        "ms",
+    ));
+    measurements.push(measurement(
+        &format!("search_depth_{}_nodes", options.depth),
+        nodes as f64 / positions.len() as f64,
+        "nodes",
+    ));
+    measurements.push(measurement(
+        &format!("search_depth_{}_ns_per_node", options.depth),
+        time.as_nanos() as f64 / nodes.max(1) as f64,
+        "ns",
+    ));
+    measurements
+}
+
+///
+/// Formats the measurements as a baseline, one `name value unit` per line.
+///
+pub fn format_baseline(measurements: &[Measurement]) -> String {
+    measurements
+        .iter()
+        .map(|m| format!("{} {:.3} {}\n", m.name, m.value, m.unit))
+        .collect()
+}
+
+pub fn parse_baseline(text: &str) -> Result<HashMap<String, f64>, Error> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(
+            |line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [name, value, _] => value
+                    .parse()
+                    .map(|value| (name.to_string(), value))
+                    .map_err(|_| Error::ParserWithMessage(line.to_string())),
+                _ => Err(Error::ParserWithMessage(line.to_string())),
+            },
+        )
+        .collect()
+}
+
+///
+/// Prints the measurements with the changes from the baseline.
+/// Returns the names of the ones which regress beyond `REGRESSION`.
+///
+pub fn report(measurements: &[Measurement], baseline: &HashMap<String, f64>) -> Vec<String> {
+    let mut regressions = Vec::new();
+    for m in measurements {
+        let change = match baseline.get(&m.name) {
+            Some(&base) if base > 0.0 => {
+                let ratio = m.value / base;
+                let flag = if ratio > REGRESSION {
+                    regressions.push(m.name.clone());
+                    "  REGRESSION"
+                } else {
+                    ""
+                };
+                format!("{:>+8.1}% from {:.3}{}", (ratio - 1.0) * 100.0, base, flag)
+            }
+            _ => String::from("     (no baseline)"),
+        };
+        println!("{:<30} {:>14.3} {:<5} {}", m.name, m.value, m.unit, change);
+    }
+    regressions
+}
+
+///
+/// Measures the hot paths on the bench positions and compares them with the baseline file,
+/// which is overwritten by the new measurements if `save`. Fails on any regression.
+///
+pub fn run_perf(options: &PerfOptions, baseline: &str, save: bool) -> Result<(), Error> {
+    let positions = bench_positions(PERF_POSITIONS, 0);
+    let measurements = measure(&positions, options);
+
+    let previous = match std::fs::read_to_string(baseline) {
+        Ok(text) => parse_baseline(&text)?,
+        Err(_) => Default::default(),
+    };
+    let regressions = report(&measurements, &previous);
+
+    if save {
+        std::fs::write(baseline, format_baseline(&measurements))?;
+        println!("The baseline is written to {}.", baseline);
+    }
+    if regressions.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Failed(format!(
+            "Regressions: {}",
+            regressions.join(", ")
+        )))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_tests() {
+        let tests = ffo_tests();
+        assert_eq!(tests[0].number, 40);
+        assert_eq!(tests[0].best, vec![from_notation("A2").unwrap()]);
+        assert_eq!(tests[0].score, 38);
+        assert_eq!(
+            64 - popcnt64!(tests[0].board.player | tests[0].board.opponent),
+            20
+        );
+
+        let tests = parse_tests("# comment\n\n1 ---------------------------OX------XO--------------------------- O D3,C4 +0").unwrap();
+        assert_eq!(tests[0].best.len(), 2);
+        assert!(parse_tests("1 --- X A1 +0").is_err());
+    }
+
+    #[test]
+    fn test_run_endgame() {
+        let board = format!("-O{}", "X".repeat(62));
+        let tests = parse_tests(&format!("1 {0} X A1 +64\n2 {0} X A1 +62", board)).unwrap();
+        assert_eq!(run_endgame(&tests, (1, 2)), (1, 2));
+        assert_eq!(run_endgame(&tests, (3, 3)), (0, 0));
+
+        // The range covers every position of the file by default.
+        let path = std::env::temp_dir().join(format!("rinee_ffo_{}.txt", std::process::id()));
+        std::fs::write(&path, format!("7 {} X A1 +64\n9 {0} X A1 +62", board)).unwrap();
+        let file = path.to_str();
+        assert!(run_ffo(file, None, Some(8)).is_ok());
+        assert!(matches!(run_ffo(file, None, None), Err(Error::Failed(_))));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_perf() {
+        let positions = bench_positions(4, 0);
+        assert_eq!(positions, bench_positions(4, 0));
+
+        let options = PerfOptions {
+            rounds: 1,
+            depth: 2,
+        };
+        let measurements = measure(&positions, &options);
+        assert!(measurements.iter().all(|m| m.value >= 0.0));
+
+        let baseline = parse_baseline(&format_baseline(&measurements)).unwrap();
+        assert_eq!(baseline.len(), measurements.len());
+        assert!(report(&measurements, &HashMap::new()).is_empty());
+
+        // The node counts are deterministic.
+        let nodes = |measurements: &[Measurement]| {
+            measurements
+                .iter()
+                .find(|m| m.name == "search_depth_2_nodes")
+                .cloned()
+                .unwrap()
+        };
+        assert_eq!(nodes(&measure(&positions, &options)), nodes(&measurements));
+
+        // A slower result than the baseline is a regression.
+        let mut slower = baseline.clone();
+        slower.insert(
+            String::from("search_depth_2_nodes"),
+            nodes(&measurements).value / 2.0,
+        );
+        assert_eq!(report(&measurements, &slower), ["search_depth_2_nodes"]);
+    }
+}
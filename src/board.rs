@@ -9,7 +9,12 @@ use crate::{popcnt64, proto::Color, tzcnt64};
 
 pub type BoardView = u64;
 
-#[derive(Clone, Copy)]
+///
+/// Stands for a pass in a sequence of moves.
+///
+pub const PASS: BoardView = 0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Board {
     pub player: BoardView,
     pub opponent: BoardView,
@@ -39,6 +44,103 @@ pub fn from_pos(view: BoardView) -> (u8, u8) {
     ((v & 0b111) as u8, (v >> 3) as u8)
 }
 
+///
+/// Converts a move into the notation like `C4`.
+///
+pub fn to_notation(view: BoardView) -> String {
+    if view == PASS {
+        String::from("PASS")
+    } else {
+        let (x, y) = from_pos(view);
+        format!("{}{}", (b'A' + x) as char, y + 1)
+    }
+}
+
+///
+/// Parses the notation like `C4` (or `c4`) and `PASS`.
+///
+pub fn from_notation(notation: &str) -> Option<BoardView> {
+    if notation.eq_ignore_ascii_case("PASS") {
+        return Some(PASS);
+    }
+    match notation.as_bytes() {
+        [x, y] => {
+            let x = x.to_ascii_uppercase().wrapping_sub(b'A');
+            let y = y.wrapping_sub(b'1');
+            if x < 8 && y < 8 {
+                Some(get_pos(x, y))
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+///
+/// Formats a sequence of moves separated by spaces.
+///
+pub fn format_line(views: &[BoardView]) -> String {
+    views
+        .iter()
+        .map(|&view| to_notation(view))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+///
+/// Parses a sequence of moves without passes like `F5D6C3`.
+///
+pub fn parse_history(history: &str) -> Option<Vec<BoardView>> {
+    if !history.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..history.len())
+        .step_by(2)
+        .map(|i| from_notation(history.get(i..i + 2)?).filter(|&view| view != PASS))
+        .collect()
+}
+
+///
+/// Parses a position of 64 squares from A1 to H8 followed by the side to move, like
+/// `---------------------------OX------XO--------------------------- X`.
+/// Black is `X` (or `B`, `*`), white is `O` (or `W`) and an empty square is `-` (or `.`).
+/// Returns the board from the viewpoint of the side to move.
+///
+pub fn parse_position(text: &str) -> Option<(Board, Color)> {
+    let mut tokens = text.split_whitespace();
+    let squares = tokens.next()?;
+    let to_move = match tokens.next()? {
+        "X" | "x" | "B" | "b" | "*" => Color::Black,
+        "O" | "o" | "W" | "w" => Color::White,
+        _ => return None,
+    };
+    if squares.len() != 64 || tokens.next().is_some() {
+        return None;
+    }
+
+    let (mut black, mut white) = (0, 0);
+    for (i, c) in squares.chars().enumerate() {
+        match c {
+            'X' | 'x' | 'B' | 'b' | '*' => black |= 1 << i,
+            'O' | 'o' | 'W' | 'w' => white |= 1 << i,
+            '-' | '.' => {}
+            _ => return None,
+        }
+    }
+    let board = match to_move {
+        Color::Black => Board {
+            player: black,
+            opponent: white,
+        },
+        Color::White => Board {
+            player: white,
+            opponent: black,
+        },
+    };
+    Some((board, to_move))
+}
+
 pub fn get_confirm_stone(me: BoardView) -> i32 {
     macro_rules! get_confirm_stone_internal {
         ($victim:expr, $shift:tt, $shift_num:expr, $mask:expr) => {
@@ -183,6 +285,90 @@ pub fn put(pos: BoardView, player: &mut BoardView, opponent: &mut BoardView) {
     *opponent ^= result;
 }
 
+///
+/// Flips the view upside down.
+///
+#[inline]
+pub fn flip_vertical(view: BoardView) -> BoardView {
+    view.swap_bytes()
+}
+
+///
+/// Flips the view left to right.
+///
+#[inline]
+pub fn flip_horizontal(view: BoardView) -> BoardView {
+    let view = ((view >> 1) & 0x5555555555555555) | ((view & 0x5555555555555555) << 1);
+    let view = ((view >> 2) & 0x3333333333333333) | ((view & 0x3333333333333333) << 2);
+    ((view >> 4) & 0x0F0F0F0F0F0F0F0F) | ((view & 0x0F0F0F0F0F0F0F0F) << 4)
+}
+
+///
+/// Flips the view along the diagonal from A1 to H8.
+///
+#[inline]
+pub fn flip_diagonal(view: BoardView) -> BoardView {
+    let t = 0x0F0F0F0F00000000 & (view ^ (view << 28));
+    let view = view ^ t ^ (t >> 28);
+    let t = 0x3333000033330000 & (view ^ (view << 14));
+    let view = view ^ t ^ (t >> 14);
+    let t = 0x5500550055005500 & (view ^ (view << 7));
+    view ^ t ^ (t >> 7)
+}
+
+///
+/// Applies one of the eight symmetries of the board.
+///
+#[inline]
+pub fn transform(view: BoardView, symmetry: u8) -> BoardView {
+    let mut view = view;
+    if symmetry & 4 != 0 {
+        view = flip_diagonal(view);
+    }
+    if symmetry & 2 != 0 {
+        view = flip_vertical(view);
+    }
+    if symmetry & 1 != 0 {
+        view = flip_horizontal(view);
+    }
+    view
+}
+
+///
+/// The inverse of `transform`.
+///
+#[inline]
+pub fn untransform(view: BoardView, symmetry: u8) -> BoardView {
+    let mut view = view;
+    if symmetry & 1 != 0 {
+        view = flip_horizontal(view);
+    }
+    if symmetry & 2 != 0 {
+        view = flip_vertical(view);
+    }
+    if symmetry & 4 != 0 {
+        view = flip_diagonal(view);
+    }
+    view
+}
+
+///
+/// Returns the representative of the symmetric boards and the symmetry leading to it.
+///
+pub fn canonicalize(board: Board) -> (Board, u8) {
+    let mut best = (board, 0);
+    for symmetry in 1..8 {
+        let candidate = Board {
+            player: transform(board.player, symmetry),
+            opponent: transform(board.opponent, symmetry),
+        };
+        if (candidate.player, candidate.opponent) < (best.0.player, best.0.opponent) {
+            best = (candidate, symmetry);
+        }
+    }
+    best
+}
+
 #[cfg(debug_assertions)]
 pub trait DebugBoard {
     fn to_string_as_board(&self, me: &Color) -> String;
@@ -226,8 +412,8 @@ impl DebugBoard for Board {
 #[macro_export]
 macro_rules! print_board {
     ($level:tt, $board:expr, $color:expr) => {{
-        use crate::board::DebugBoard;
-        crate::write_log!($level, "{}", $board.to_string_as_board($color));
+        use $crate::board::DebugBoard;
+        $crate::write_log!($level, "{}", $board.to_string_as_board($color));
     }};
 }
 
@@ -246,6 +432,53 @@ mod test {
         assert_eq!(from_pos(get_pos(5, 2)), (5, 2));
     }
 
+    #[test]
+    fn test_notation() {
+        assert_eq!(to_notation(get_pos(2, 3)), "C4");
+        assert_eq!(from_notation("C4"), Some(get_pos(2, 3)));
+        assert_eq!(from_notation("h8"), Some(get_pos(7, 7)));
+        assert_eq!(from_notation("PASS"), Some(PASS));
+        assert_eq!(from_notation("I1"), None);
+        assert_eq!(format_line(&[get_pos(2, 3), PASS]), "C4 PASS");
+        assert_eq!(
+            parse_history("F5d6"),
+            Some(vec![get_pos(5, 4), get_pos(3, 5)])
+        );
+        assert_eq!(parse_history("F5D"), None);
+
+        let initial = "---------------------------OX------XO--------------------------- X";
+        assert_eq!(
+            parse_position(initial),
+            Some((new_board(&Color::Black), Color::Black))
+        );
+        assert_eq!(parse_position("---- X"), None);
+    }
+
+    #[test]
+    fn test_symmetry() {
+        assert_eq!(flip_vertical(get_pos(2, 3)), get_pos(2, 4));
+        assert_eq!(flip_horizontal(get_pos(2, 3)), get_pos(5, 3));
+        assert_eq!(flip_diagonal(get_pos(2, 3)), get_pos(3, 2));
+        for symmetry in 0..8 {
+            let view = 0x0123_4567_89AB_CDEF;
+            assert_eq!(untransform(transform(view, symmetry), symmetry), view);
+        }
+
+        // Every first move of black leads to the same position.
+        let mut canonical = Vec::new();
+        let mut valid = get_valid_moves(0x0000000810000000, 0x0000001008000000);
+        while valid != 0 {
+            let view = 1 << tzcnt64!(valid);
+            valid ^= view;
+
+            let mut board = new_board(&Color::Black);
+            put(view, &mut board.player, &mut board.opponent);
+            canonical.push(canonicalize(board).0);
+        }
+        assert_eq!(canonical.len(), 4);
+        assert!(canonical.iter().all(|&board| board == canonical[0]));
+    }
+
     #[test]
     fn test_new_board() {
         let board = new_board(&Color::Black);
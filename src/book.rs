@@ -0,0 +1,1070 @@
+//
+// An opening book keyed by canonical positions.
+// Transpositions and all the eight symmetries of a position share an entry.
+//
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    path::PathBuf,
+    sync::{atomic::AtomicBool, Arc},
+    thread,
+};
+
+use crate::{
+    agent::{SearchOptions, Searcher, INF, VALUE_PER_DISC},
+    board::{
+        canonicalize, from_notation, get_valid_moves, new_board, parse_history, put, to_notation,
+        transform, untransform, Board, BoardView,
+    },
+    ggf::PlayedGame,
+    packed_book::{PackedBook, MAGIC},
+    proto::{Color, Error},
+    util::Rng,
+    write_log,
+    wtb::GameRecord,
+    Args,
+};
+
+pub static DEFAULT_BOOK_FILE: &str = "preprocessed.txt";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BookMove {
+    pub view: BoardView,
+    ///
+    /// The number of games in which the move was played.
+    ///
+    pub games: u32,
+    ///
+    /// The average disc differential for the side to move.
+    ///
+    pub score: f64,
+    ///
+    /// The value backed up from the searches on the leaves of the book, for the side to move.
+    ///
+    pub value: Option<i32>,
+}
+
+#[derive(Default)]
+pub struct Book {
+    ///
+    /// Moves on canonical positions, in the orientation of the canonical ones.
+    ///
+    entries: HashMap<Board, Vec<BookMove>>,
+}
+
+impl Book {
+    pub fn iter(&self) -> impl Iterator<Item = (&Board, &Vec<BookMove>)> {
+        self.entries.iter()
+    }
+
+    ///
+    /// Records games where the move was played on the board, merging them into the statistics.
+    ///
+    pub fn add(&mut self, board: Board, view: BoardView, games: u32, score: f64) {
+        let (canonical, symmetry) = canonicalize(board);
+        let view = transform(view, symmetry);
+        let moves = self.entries.entry(canonical).or_default();
+        match moves.iter_mut().find(|mv| mv.view == view) {
+            Some(mv) => {
+                let total = mv.games + games;
+                if total > 0 {
+                    mv.score = (mv.score * mv.games as f64 + score * games as f64) / total as f64;
+                }
+                mv.games = total;
+            }
+            None => moves.push(BookMove {
+                view,
+                games,
+                score,
+                value: None,
+            }),
+        }
+    }
+
+    pub fn set_value(&mut self, board: Board, view: BoardView, value: i32) {
+        let (canonical, symmetry) = canonicalize(board);
+        let view = transform(view, symmetry);
+        if let Some(mv) = self
+            .entries
+            .get_mut(&canonical)
+            .and_then(|moves| moves.iter_mut().find(|mv| mv.view == view))
+        {
+            mv.value = Some(value);
+        }
+    }
+
+    ///
+    /// Parses a book. Each line is either
+    /// - `player opponent move games score [value]` where the boards are hexadecimal, or
+    /// - `history move` of the former preprocessed table, where `history` is like `F5D6C3`.
+    ///
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut book = Book::default();
+        for line in text.lines() {
+            book.parse_line(line)?;
+        }
+        Ok(book)
+    }
+
+    ///
+    /// Parses a book like `parse`, but skips the broken lines and returns them
+    /// with their line numbers from 1.
+    ///
+    pub fn parse_lenient(text: &str) -> (Self, Vec<(usize, String)>) {
+        let mut book = Book::default();
+        let mut broken = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            if book.parse_line(line).is_err() {
+                broken.push((i + 1, line.to_string()));
+            }
+        }
+        (book, broken)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), Error> {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        let error = || Error::ParserWithMessage(line.to_string());
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens[..] {
+            [player, opponent, view, games, score, ref value @ ..] if value.len() <= 1 => {
+                let board = Board {
+                    player: u64::from_str_radix(player, 16).map_err(|_| error())?,
+                    opponent: u64::from_str_radix(opponent, 16).map_err(|_| error())?,
+                };
+                let view = from_notation(view).ok_or_else(error)?;
+                let games = games.parse().map_err(|_| error())?;
+                let score = score.parse().map_err(|_| error())?;
+                let value = match value {
+                    [value] => Some(value.parse().map_err(|_| error())?),
+                    _ => None,
+                };
+                self.add(board, view, games, score);
+                if let Some(value) = value {
+                    self.set_value(board, view, value);
+                }
+            }
+            [history, view] => {
+                let board = replay(history).ok_or_else(error)?;
+                let view = from_notation(view).ok_or_else(error)?;
+                self.add(board, view, 1, 0.0);
+            }
+            _ => return Err(error()),
+        }
+        Ok(())
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut entries: Vec<_> = self.entries.iter().collect();
+        entries.sort_by_key(|(board, _)| (board.player, board.opponent));
+
+        let mut text = String::from("# player opponent move games score [value]\n");
+        for (board, moves) in entries {
+            let mut moves = moves.clone();
+            moves.sort_by_key(|mv| mv.view);
+            for mv in moves {
+                text += &format!(
+                    "{:016X} {:016X} {} {} {:.2}",
+                    board.player,
+                    board.opponent,
+                    to_notation(mv.view),
+                    mv.games,
+                    mv.score
+                );
+                if let Some(value) = mv.value {
+                    text += &format!(" {}", value);
+                }
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    ///
+    /// Returns the board where the book continues after the move, and whether the side to move
+    /// there is the same as the one on `board` because of a pass.
+    ///
+    fn next_position(board: Board, view: BoardView) -> (Board, bool) {
+        let mut next = board;
+        put(view, &mut next.player, &mut next.opponent);
+        if get_valid_moves(next.opponent, next.player) == 0
+            && get_valid_moves(next.player, next.opponent) != 0
+        {
+            (next, true)
+        } else {
+            (
+                Board {
+                    player: next.opponent,
+                    opponent: next.player,
+                },
+                false,
+            )
+        }
+    }
+
+    ///
+    /// Searches every leaf of the book to the depth, which is at least 1,
+    /// and backs the values up with negamax.
+    ///
+    pub fn deepen(&mut self, depth: u8, options: &SearchOptions) {
+        // Moves leaving the book.
+        let mut leaves = Vec::new();
+        for (&board, moves) in &self.entries {
+            for mv in moves {
+                let (next, _) = Book::next_position(board, mv.view);
+                if !self.entries.contains_key(&canonicalize(next).0) {
+                    leaves.push((board, mv.view));
+                }
+            }
+        }
+        write_log!(LOG, "Searching {} leaves at depth {}.", leaves.len(), depth);
+
+        let workers = thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk = leaves.len().div_ceil(workers).max(1);
+        let leaf_values: HashMap<(Board, BoardView), i32> = thread::scope(|scope| {
+            let handles: Vec<_> = leaves
+                .chunks(chunk)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        let mut searcher = Searcher::new(
+                            Arc::new(AtomicBool::new(false)),
+                            options.probcut.clone(),
+                            options.tt.clone(),
+                        );
+                        let mut pv = Vec::new();
+                        chunk
+                            .iter()
+                            .map(|&(board, view)| {
+                                let mut next = board;
+                                put(view, &mut next.player, &mut next.opponent);
+                                let value = searcher
+                                    .alpha_beta(next, false, depth - 1, -INF, INF, &mut pv)
+                                    .expect("the search is never interrupted");
+                                ((board, view), value)
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        });
+
+        let mut memo = HashMap::new();
+        let boards: Vec<Board> = self.entries.keys().copied().collect();
+        for board in boards {
+            self.negamax(board, &leaf_values, &mut memo);
+        }
+    }
+
+    ///
+    /// Returns the value of the canonical board, filling the values of its moves.
+    ///
+    fn negamax(
+        &mut self,
+        board: Board,
+        leaf_values: &HashMap<(Board, BoardView), i32>,
+        memo: &mut HashMap<Board, i32>,
+    ) -> i32 {
+        if let Some(&value) = memo.get(&board) {
+            return value;
+        }
+
+        let moves = self.entries[&board].clone();
+        let mut best = -INF;
+        for (i, mv) in moves.iter().enumerate() {
+            let value = match leaf_values.get(&(board, mv.view)) {
+                Some(&value) => value,
+                None => {
+                    let (next, same_side) = Book::next_position(board, mv.view);
+                    let value = self.negamax(canonicalize(next).0, leaf_values, memo);
+                    if same_side {
+                        value
+                    } else {
+                        -value
+                    }
+                }
+            };
+            self.entries.get_mut(&board).unwrap()[i].value = Some(value);
+            best = best.max(value);
+        }
+
+        memo.insert(board, best);
+        best
+    }
+
+    ///
+    /// Returns the positions where the move the games favor is worse than the best one by the search
+    /// more than the margin: `(board, favored, best)`.
+    ///
+    pub fn refuted(&self, margin: i32) -> Vec<(Board, BookMove, BookMove)> {
+        let mut refuted = Vec::new();
+        for (&board, moves) in &self.entries {
+            let favored = moves.iter().max_by(|a, b| {
+                a.score
+                    .total_cmp(&b.score)
+                    .then_with(|| a.games.cmp(&b.games))
+            });
+            let best = moves.iter().max_by_key(|mv| mv.value);
+            if let (Some(favored), Some(best)) = (favored, best) {
+                if let (Some(favored_value), Some(best_value)) = (favored.value, best.value) {
+                    if best_value.saturating_sub(favored_value) > margin {
+                        refuted.push((board, *favored, *best));
+                    }
+                }
+            }
+        }
+        refuted.sort_by_key(|(board, _, _)| (board.player, board.opponent));
+        refuted
+    }
+
+    ///
+    /// Returns the moves which cannot be played on their positions with the reasons.
+    ///
+    pub fn verify(&self) -> Vec<(Board, BookMove, &'static str)> {
+        let mut invalid = Vec::new();
+        for (&board, moves) in &self.entries {
+            let valid = get_valid_moves(board.player, board.opponent);
+            for mv in moves {
+                let reason = if board.player & board.opponent != 0 {
+                    "the discs overlap"
+                } else if valid == 0 {
+                    "the side to move has to pass"
+                } else if mv.view & valid == 0 {
+                    "the move is illegal"
+                } else {
+                    continue;
+                };
+                invalid.push((board, *mv, reason));
+            }
+        }
+        invalid.sort_by_key(|(board, mv, _)| (board.player, board.opponent, mv.view));
+        invalid
+    }
+
+    ///
+    /// Learns from the games played by the client. Moves which are not in the book are added
+    /// unless they lost more games than they won, which extends the book along our lines.
+    /// The games of moves in the book are merged into their statistics. The moves which lost in
+    /// most of at least `min_games` games are also penalized: their scores and values are capped
+    /// by the ones of the games, so that the engine stops repeating them.
+    ///
+    pub fn learn(&mut self, games: &[PlayedGame], options: &LearnOptions) -> LearnSummary {
+        // (games, the sum of disc differentials, wins, losses) for the side to move.
+        let mut stats: HashMap<(Board, BoardView), (u32, f64, u32, u32)> = HashMap::new();
+        let games = games.iter().filter(|game| {
+            options
+                .opponent
+                .as_ref()
+                .is_none_or(|opponent| &game.opponent == opponent)
+        });
+        for game in games {
+            let Some(moves) = game.moves() else {
+                continue;
+            };
+            let moves = &moves[..moves.len().min(options.depth)];
+            let black_diff = match game.color {
+                Color::Black => game.diff(),
+                Color::White => -game.diff(),
+            };
+
+            // The first move is not a matter of the book.
+            for (board, view, black_to_move) in positions(moves).into_iter().skip(1) {
+                let diff = if black_to_move {
+                    black_diff
+                } else {
+                    -black_diff
+                };
+                let (canonical, symmetry) = canonicalize(board);
+                let entry = stats
+                    .entry((canonical, transform(view, symmetry)))
+                    .or_default();
+                entry.0 += 1;
+                entry.1 += diff as f64;
+                entry.2 += (diff > 0) as u32;
+                entry.3 += (diff < 0) as u32;
+            }
+        }
+
+        let mut summary = LearnSummary::default();
+        for ((board, view), (games, total, wins, losses)) in stats {
+            let score = total / games as f64;
+            let moves = self.entries.entry(board).or_default();
+            match moves.iter_mut().find(|mv| mv.view == view) {
+                Some(mv) => {
+                    let merged = mv.games + games;
+                    mv.score = (mv.score * mv.games as f64 + total) / merged as f64;
+                    mv.games = merged;
+                    summary.merged += 1;
+                    if games >= options.min_games && losses * 2 > games {
+                        mv.score = mv.score.min(score);
+                        let value = (score * VALUE_PER_DISC as f64).round() as i32;
+                        mv.value = mv.value.map(|old| old.min(value));
+                        summary.penalized += 1;
+                    }
+                }
+                None => {
+                    if losses <= wins {
+                        moves.push(BookMove {
+                            view,
+                            games,
+                            score,
+                            value: None,
+                        });
+                        summary.added += 1;
+                    }
+                }
+            }
+        }
+        self.entries.retain(|_, moves| !moves.is_empty());
+        summary
+    }
+
+    pub fn load(path: &str) -> Result<Self, Error> {
+        Book::parse(&std::fs::read_to_string(path)?)
+    }
+}
+
+///
+/// Opening books which the client can look moves up in.
+///
+pub trait OpeningBook: Send + Sync {
+    ///
+    /// The number of positions.
+    ///
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///
+    /// Returns the moves on the board in its own orientation.
+    ///
+    fn lookup(&self, board: Board) -> Vec<BookMove>;
+
+    ///
+    /// Returns the move with the best value if the book has been deepened,
+    /// and the one with the best score otherwise, preferring the more frequent one on ties.
+    ///
+    fn best_move(&self, board: Board) -> Option<BookMove> {
+        let moves = self.lookup(board);
+        if moves.iter().any(|mv| mv.value.is_some()) {
+            moves
+                .into_iter()
+                .filter(|mv| mv.value.is_some())
+                .max_by_key(|mv| (mv.value, mv.games))
+        } else {
+            moves.into_iter().max_by(|a, b| {
+                a.score
+                    .total_cmp(&b.score)
+                    .then_with(|| a.games.cmp(&b.games))
+            })
+        }
+    }
+
+    ///
+    /// Chooses a move at random among the ones within `margin` discs from the best,
+    /// weighted by the number of games. The move is also mapped at random to one of its
+    /// equivalents when the board is symmetric.
+    ///
+    fn choose_move(&self, board: Board, margin: f64, rng: &mut Rng) -> Option<BookMove> {
+        let best = self.best_move(board)?;
+        let candidates: Vec<BookMove> = if margin <= 0.0 {
+            vec![best]
+        } else {
+            self.lookup(board)
+                .into_iter()
+                .filter(|mv| match (mv.value, best.value) {
+                    (Some(value), Some(best)) => {
+                        (best.saturating_sub(value) as f64) <= margin * VALUE_PER_DISC as f64
+                    }
+                    (None, Some(_)) => false,
+                    _ => best.score - mv.score <= margin,
+                })
+                .collect()
+        };
+
+        let total: u64 = candidates.iter().map(|mv| mv.games.max(1) as u64).sum();
+        let mut pick = rng.below(total);
+        let mut chosen = best;
+        for mv in candidates {
+            let weight = mv.games.max(1) as u64;
+            if pick < weight {
+                chosen = mv;
+                break;
+            }
+            pick -= weight;
+        }
+
+        let symmetries: Vec<u8> = (0..8)
+            .filter(|&symmetry| {
+                transform(board.player, symmetry) == board.player
+                    && transform(board.opponent, symmetry) == board.opponent
+            })
+            .collect();
+        let symmetry = symmetries[rng.below(symmetries.len() as u64) as usize];
+        Some(BookMove {
+            view: transform(chosen.view, symmetry),
+            ..chosen
+        })
+    }
+}
+
+impl OpeningBook for Book {
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn lookup(&self, board: Board) -> Vec<BookMove> {
+        let (canonical, symmetry) = canonicalize(board);
+        match self.entries.get(&canonical) {
+            Some(moves) => moves
+                .iter()
+                .map(|mv| BookMove {
+                    view: untransform(mv.view, symmetry),
+                    ..*mv
+                })
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+pub struct BuildOptions {
+    ///
+    /// The number of moves read from the beginning of each game.
+    ///
+    pub depth: usize,
+    ///
+    /// Positions appearing in fewer games than this are dropped.
+    ///
+    pub threshold: u32,
+    ///
+    /// Whether games are pooled over all the symmetric positions before applying the threshold.
+    /// Otherwise only identical positions are pooled.
+    ///
+    pub symmetry: bool,
+}
+
+pub struct LearnOptions {
+    ///
+    /// The number of moves read from the beginning of each game.
+    ///
+    pub depth: usize,
+    ///
+    /// Moves in the book are penalized only after they are played in this many games.
+    ///
+    pub min_games: u32,
+    ///
+    /// Only the games against this opponent are learned if any, since a line refuted by one
+    /// opponent may still be good against the others.
+    ///
+    pub opponent: Option<String>,
+}
+
+#[derive(Default)]
+pub struct LearnSummary {
+    pub added: usize,
+    pub merged: usize,
+    pub penalized: usize,
+}
+
+///
+/// Builds a book from game records.
+/// Scores are the theoretical disc differentials of the games (`black_best`) for the side to move.
+///
+pub fn build(records: &[GameRecord], options: &BuildOptions) -> Book {
+    let mut stats: HashMap<Board, HashMap<BoardView, (u32, f64)>> = HashMap::new();
+
+    for record in records {
+        let black_diff = 2.0 * record.black_best as f64 - 64.0;
+        let moves = &record.moves[..record.moves.len().min(options.depth)];
+
+        // The first move is not a matter of the book.
+        for (board, view, black_to_move) in positions(moves).into_iter().skip(1) {
+            let (key, view) = if options.symmetry {
+                let (canonical, symmetry) = canonicalize(board);
+                (canonical, transform(view, symmetry))
+            } else {
+                (board, view)
+            };
+            let score = if black_to_move {
+                black_diff
+            } else {
+                -black_diff
+            };
+            let entry = stats.entry(key).or_default().entry(view).or_default();
+            entry.0 += 1;
+            entry.1 += score;
+        }
+    }
+
+    let mut book = Book::default();
+    for (board, moves) in stats {
+        let appeared: u32 = moves.values().map(|(games, _)| games).sum();
+        if appeared < options.threshold {
+            continue;
+        }
+        for (view, (games, total)) in moves {
+            book.add(board, view, games, total / games as f64);
+        }
+    }
+    book
+}
+
+///
+/// Replays the moves like `F5D6C3` from the initial position and returns the board
+/// from the viewpoint of the side to move.
+///
+pub fn replay(history: &str) -> Option<Board> {
+    replay_to_move(history).map(|(board, _)| board)
+}
+
+///
+/// Replays the moves like `replay` and also returns the side to move.
+///
+pub fn replay_to_move(history: &str) -> Option<(Board, Color)> {
+    let moves = parse_history(history)?;
+    let played = positions(&moves);
+    if played.len() < moves.len() {
+        return None;
+    }
+
+    match played.last() {
+        Some(&(board, view, black_to_move)) => {
+            let mut board = board;
+            put(view, &mut board.player, &mut board.opponent);
+            let board = Board {
+                player: board.opponent,
+                opponent: board.player,
+            };
+            Some((
+                board,
+                if black_to_move {
+                    Color::White
+                } else {
+                    Color::Black
+                },
+            ))
+        }
+        None => Some((new_board(&Color::Black), Color::Black)),
+    }
+}
+
+///
+/// Replays the moves from the initial position, letting the side without a legal move pass.
+/// Returns the boards from the viewpoint of the side to move with the moves played on them
+/// and whether black is to move. The moves after an illegal one are dropped.
+///
+fn positions(moves: &[BoardView]) -> Vec<(Board, BoardView, bool)> {
+    let mut played = Vec::new();
+    let mut board = new_board(&Color::Black);
+    let mut black_to_move = true;
+    for &view in moves {
+        let valid = get_valid_moves(board.player, board.opponent);
+        if valid & view == 0 {
+            // Only the side without a legal move passes.
+            if valid != 0 {
+                break;
+            }
+            board = Board {
+                player: board.opponent,
+                opponent: board.player,
+            };
+            black_to_move = !black_to_move;
+            if get_valid_moves(board.player, board.opponent) & view == 0 {
+                break;
+            }
+        }
+        played.push((board, view, black_to_move));
+
+        put(view, &mut board.player, &mut board.opponent);
+        board = Board {
+            player: board.opponent,
+            opponent: board.player,
+        };
+        black_to_move = !black_to_move;
+    }
+    played
+}
+
+///
+/// Candidates of the book file: the path as it is, and the one next to the executable.
+///
+fn candidates(path: &str) -> Vec<PathBuf> {
+    let mut candidates = vec![PathBuf::from(path)];
+    if PathBuf::from(path).is_relative() {
+        if let Some(dir) = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.to_path_buf()))
+        {
+            candidates.push(dir.join(path));
+        }
+    }
+    candidates
+}
+
+///
+/// Loads a book either in the text format or in the binary one.
+///
+pub fn open_book(path: &str) -> Result<Box<dyn OpeningBook>, Error> {
+    let bytes = std::fs::read(path)?;
+    if bytes.starts_with(MAGIC) {
+        Ok(Box::new(PackedBook::new(Cow::Owned(bytes))?))
+    } else {
+        let text = String::from_utf8(bytes).map_err(|_| Error::Parser)?;
+        Ok(Box::new(Book::parse(&text)?))
+    }
+}
+
+///
+/// Loads a book in either format to verify it. The broken lines of a text book are returned
+/// with their line numbers instead of failing on the first one.
+///
+fn load_for_verify(path: &str) -> Result<(Book, Vec<(usize, String)>), Error> {
+    let bytes = std::fs::read(path)?;
+    if bytes.starts_with(MAGIC) {
+        Ok((PackedBook::new(Cow::Owned(bytes))?.unpack(), Vec::new()))
+    } else {
+        let text = String::from_utf8(bytes).map_err(|_| Error::Parser)?;
+        Ok(Book::parse_lenient(&text))
+    }
+}
+
+///
+/// Verifies a book in either format, printing the broken lines and the invalid moves.
+/// Fails if any of them is found.
+///
+pub fn verify(path: &str) -> Result<(), Error> {
+    let (book, broken) = load_for_verify(path)?;
+    for (number, line) in &broken {
+        println!("line {}: cannot parse \"{}\"", number, line);
+    }
+    let invalid = book.verify();
+    for (board, mv, reason) in &invalid {
+        println!(
+            "{:016X} {:016X} {}: {}",
+            board.player,
+            board.opponent,
+            to_notation(mv.view),
+            reason
+        );
+    }
+    let summary = format!(
+        "{} broken lines and {} invalid moves in {} positions of {}.",
+        broken.len(),
+        invalid.len(),
+        book.len(),
+        path
+    );
+    if broken.is_empty() && invalid.is_empty() {
+        println!("{}", summary);
+        Ok(())
+    } else {
+        Err(Error::Failed(summary))
+    }
+}
+
+///
+/// Loads the book specified by the command line, or the embedded one if omitted.
+/// An empty book is returned when it is disabled or broken, so that the engine can still play.
+///
+pub fn load_book(args: &Args) -> Box<dyn OpeningBook> {
+    if args.no_book {
+        return Box::new(Book::default());
+    }
+
+    let Some(book) = &args.book else {
+        let book = PackedBook::embedded();
+        write_log!(
+            DEBUG,
+            "Using the embedded book of {} positions.",
+            book.len()
+        );
+        return Box::new(book);
+    };
+
+    for candidate in candidates(book) {
+        if !candidate.exists() {
+            continue;
+        }
+        let path = candidate.to_string_lossy();
+        match open_book(&path) {
+            Ok(book) => {
+                if book.is_empty() {
+                    write_log!(WARN, "The book {} is empty.", path);
+                } else {
+                    write_log!(DEBUG, "Loaded {} positions from {}.", book.len(), path);
+                }
+                return book;
+            }
+            Err(Error::ParserWithMessage(line)) => {
+                write_log!(WARN, "The book {} is broken at \"{}\".", path, line);
+                return Box::new(Book::default());
+            }
+            Err(_) => {
+                write_log!(WARN, "Failed to read the book {}.", path);
+                return Box::new(Book::default());
+            }
+        }
+    }
+
+    write_log!(
+        WARN,
+        "The book {} is not found. Using the embedded one.",
+        book
+    );
+    Box::new(PackedBook::embedded())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{board::get_pos, proto::GameResult};
+
+    #[test]
+    fn test_symmetric_lookup() {
+        let book = Book::parse("F5 D6\n").unwrap();
+
+        // C4 is symmetric to F5, and E3 is the reply corresponding to D6.
+        let board = replay("C4").unwrap();
+        let moves = book.lookup(board);
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].view, get_pos(4, 2));
+    }
+
+    #[test]
+    fn test_replay_rejects_false_pass() {
+        // C4 is legal for black but not for white, who has legal moves and cannot pass.
+        assert!(replay("F5C4").is_none());
+        assert!(replay("F5D6C4").is_some());
+    }
+
+    #[test]
+    fn test_add() {
+        let mut book = Book::default();
+        let board = replay("F5D6").unwrap();
+        book.add(board, from_notation("C3").unwrap(), 3, 2.0);
+        book.add(board, from_notation("C3").unwrap(), 1, -2.0);
+        book.add(board, from_notation("E3").unwrap(), 1, 0.5);
+
+        assert_eq!(
+            book.best_move(board),
+            Some(BookMove {
+                view: from_notation("C3").unwrap(),
+                games: 4,
+                score: 1.0,
+                value: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_build() {
+        let (_, records) =
+            crate::wtb::parse_wtb(&crate::wtb::test::encode_single(&[56, 64, 33], 40, 36)).unwrap();
+        let mut records = vec![records[0].clone(), records[0].clone()];
+        // The same game in the symmetric orientation.
+        records[1].moves = records[1]
+            .moves
+            .iter()
+            .map(|&view| transform(view, 3))
+            .collect();
+
+        let options = BuildOptions {
+            depth: 20,
+            threshold: 2,
+            symmetry: true,
+        };
+        let book = Book::parse(&build(&records, &options).to_text()).unwrap();
+        assert_eq!(
+            book.best_move(replay("F5D6").unwrap()),
+            Some(BookMove {
+                view: from_notation("C3").unwrap(),
+                games: 2,
+                score: 8.0,
+                value: None,
+            })
+        );
+
+        let options = BuildOptions {
+            symmetry: false,
+            ..options
+        };
+        assert!(build(&records, &options).is_empty());
+    }
+
+    #[test]
+    fn test_choose_move() {
+        let mut book = Book::default();
+        let board = replay("F5D6").unwrap();
+        book.add(board, from_notation("C3").unwrap(), 10, 2.0);
+        book.add(board, from_notation("E3").unwrap(), 10, 1.0);
+        book.add(board, from_notation("C5").unwrap(), 10, -4.0);
+
+        let mut rng = Rng::new(0);
+        let mut chosen = Vec::new();
+        for _ in 0..100 {
+            chosen.push(book.choose_move(board, 1.5, &mut rng).unwrap().view);
+        }
+        assert!(chosen.contains(&from_notation("C3").unwrap()));
+        assert!(chosen.contains(&from_notation("E3").unwrap()));
+        assert!(!chosen.contains(&from_notation("C5").unwrap()));
+
+        // Moves on the symmetric initial position are spread over the equivalents.
+        let mut book = Book::default();
+        book.add(replay("").unwrap(), from_notation("F5").unwrap(), 1, 0.0);
+        let mut chosen = Vec::new();
+        for _ in 0..100 {
+            let view = book
+                .choose_move(replay("").unwrap(), 0.0, &mut rng)
+                .unwrap()
+                .view;
+            if !chosen.contains(&view) {
+                chosen.push(view);
+            }
+        }
+        assert_eq!(chosen.len(), 4);
+    }
+
+    #[test]
+    fn test_deepen() {
+        let mut book = Book::default();
+        let board = replay("F5").unwrap();
+        book.add(board, from_notation("D6").unwrap(), 10, 4.0);
+        book.add(board, from_notation("F6").unwrap(), 1, -4.0);
+        let next = replay("F5D6").unwrap();
+        book.add(next, from_notation("C3").unwrap(), 10, 0.0);
+        book.deepen(3, &SearchOptions::default());
+
+        let mut searcher = Searcher::new(Arc::new(AtomicBool::new(false)), None, None);
+        let mut pv = Vec::new();
+        let leaf = replay("F5D6C3").unwrap();
+        let expected = searcher
+            .alpha_beta(leaf, true, 2, -INF, INF, &mut pv)
+            .unwrap();
+
+        // The value is backed up through the move of the opponent.
+        let moves = book.lookup(board);
+        let d6 = moves
+            .iter()
+            .find(|mv| mv.view == from_notation("D6").unwrap());
+        assert_eq!(d6.unwrap().value, Some(expected));
+        assert!(moves.iter().all(|mv| mv.value.is_some()));
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut book = Book::load(DEFAULT_BOOK_FILE).unwrap();
+        assert!(book.verify().is_empty());
+
+        let board = replay("F5D6").unwrap();
+        book.add(board, from_notation("A1").unwrap(), 1, 0.0);
+        let invalid = book.verify();
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(invalid[0].2, "the move is illegal");
+
+        let (book, broken) = Book::parse_lenient("F5 D6\nF5 Z9\n# comment\nF5D6 C3 1\nF5D6 C3");
+        assert_eq!(book.len(), 2);
+        assert_eq!(
+            broken,
+            [(2, String::from("F5 Z9")), (4, String::from("F5D6 C3 1"))]
+        );
+
+        assert!(verify(DEFAULT_BOOK_FILE).is_ok());
+        let path = std::env::temp_dir().join(format!("rinee_verify_{}.txt", std::process::id()));
+        std::fs::write(&path, "F5 D6\nF5 Z9\n").unwrap();
+        let result = verify(&path.to_string_lossy());
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(Error::Failed(_))));
+    }
+
+    #[test]
+    fn test_learn() {
+        let mut book = Book::default();
+        let board = replay("F5D6").unwrap();
+        book.add(board, from_notation("C3").unwrap(), 100, 2.0);
+        book.add(board, from_notation("C5").unwrap(), 100, 1.0);
+
+        let lost = PlayedGame {
+            opponent: String::from("someone"),
+            color: Color::Black,
+            result: GameResult::Lose,
+            score: 30,
+            opponent_score: 34,
+            history: String::from("F5D6C3D3"),
+        };
+        let won = PlayedGame {
+            result: GameResult::Win,
+            score: 40,
+            opponent_score: 24,
+            history: String::from("F5D6C5F4E3"),
+            ..lost.clone()
+        };
+        let options = LearnOptions {
+            depth: 20,
+            min_games: 2,
+            opponent: None,
+        };
+
+        // A single loss is not enough to replace the move, but the book is extended
+        // with the moves of the winners.
+        let summary = book.learn(&[lost.clone(), won], &options);
+        assert_eq!(
+            (summary.added, summary.merged, summary.penalized),
+            (3, 2, 0)
+        );
+        let c3 = book.lookup(board)[0];
+        assert_eq!(c3.view, from_notation("C3").unwrap());
+        assert_eq!(c3.games, 101);
+        assert_eq!(c3.score, (200.0 - 4.0) / 101.0);
+        assert_eq!(
+            book.best_move(board).unwrap().view,
+            from_notation("C3").unwrap()
+        );
+        assert_eq!(
+            book.best_move(replay("F5D6C5F4").unwrap()).unwrap().view,
+            from_notation("E3").unwrap()
+        );
+        assert_eq!(
+            book.best_move(replay("F5D6C3").unwrap()).unwrap().view,
+            from_notation("D3").unwrap()
+        );
+        assert!(book.best_move(replay("F5D6C5").unwrap()).is_none());
+
+        // The losses against another opponent are not learned.
+        let others = LearnOptions {
+            opponent: Some(String::from("another")),
+            ..options
+        };
+        let summary = book.learn(&[lost.clone(), lost.clone()], &others);
+        assert_eq!(
+            (summary.added, summary.merged, summary.penalized),
+            (0, 0, 0)
+        );
+
+        // The counts are kept while the refuted move is penalized.
+        let summary = book.learn(&[lost.clone(), lost], &options);
+        assert_eq!(summary.penalized, 1);
+        let c3 = book.lookup(board)[0];
+        assert_eq!((c3.games, c3.score), (103, -4.0));
+        assert_eq!(
+            book.best_move(board).unwrap().view,
+            from_notation("C5").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_load_preprocessed() {
+        let book = Book::load(DEFAULT_BOOK_FILE).unwrap();
+        assert!(!book.is_empty());
+        assert!(book.best_move(replay("F5").unwrap()).is_some());
+    }
+}
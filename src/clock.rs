@@ -0,0 +1,507 @@
+//
+// Time management.
+// The remaining clock is shared among our remaining moves, weighted by the phase of the game,
+// after reserving a margin for the network latency of every move.
+// Each move gets a soft limit, which the search may extend when it is unsure of the best move,
+// and a hard limit, which it never exceeds.
+//
+
+use std::{
+    sync::{atomic::AtomicBool, Arc},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    agent::{best_move_at_depth, Choice, SearchResult, Searcher, INF, VALUE_PER_DISC},
+    board::{get_valid_moves, Board},
+    endgame::ordered_moves,
+    popcnt64,
+    stats::SearchStats,
+};
+
+///
+/// A hard limit shorter than this leaves no time to search, and an instant move is played instead.
+///
+pub const EMERGENCY_LIMIT: Duration = Duration::from_millis(20);
+
+///
+/// The depth of the search of an instant move.
+///
+const EMERGENCY_DEPTH: u8 = 3;
+
+///
+/// The time never spent, unless the clock is short.
+///
+const RESERVE: Duration = Duration::from_millis(1000);
+
+///
+/// The hard limit is at most this many times the soft limit.
+///
+const HARD_RATIO: f64 = 4.0;
+
+///
+/// The hard limit is at most this part of the available time.
+///
+const HARD_SHARE: f64 = 0.4;
+
+///
+/// The soft limit is extended by this ratio when the search is unsure of the best move.
+///
+const EXTENSION: f64 = 2.0;
+
+///
+/// A drop of the best score extending the search.
+///
+const SCORE_DROP: i32 = 2 * VALUE_PER_DISC;
+
+///
+/// The margin by which the best move dominates the others to stop the search early.
+///
+const DOMINANCE: i32 = 8 * VALUE_PER_DISC;
+
+///
+/// The depth every move has to complete before the search stops early.
+///
+const DOMINANCE_DEPTH: u8 = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeLimit {
+    ///
+    /// The time the search is expected to take.
+    ///
+    pub soft: Duration,
+    ///
+    /// The time the search must not exceed.
+    ///
+    pub hard: Duration,
+}
+
+impl TimeLimit {
+    ///
+    /// A limit using up the duration, which is never extended nor cut short.
+    ///
+    pub fn fixed(duration: Duration) -> Self {
+        TimeLimit {
+            soft: duration,
+            hard: duration,
+        }
+    }
+
+    pub fn is_emergency(&self) -> bool {
+        self.hard < EMERGENCY_LIMIT
+    }
+}
+
+///
+/// Chooses a move without the time to search: the best one of a shallow search,
+/// or the one leaving the opponent the fewest moves if the search does not complete in time.
+///
+pub fn emergency_move(board: Board) -> SearchResult {
+    let start = Instant::now();
+    let valid = get_valid_moves(board.player, board.opponent);
+    if valid == 0 {
+        return SearchResult {
+            best: None,
+            score: None,
+            stats: SearchStats::default(),
+        };
+    }
+
+    let mut searcher = Searcher::new(Arc::new(AtomicBool::new(false)), None, None)
+        .with_deadline(start + EMERGENCY_LIMIT / 2);
+    let (best, score) = match best_move_at_depth(&mut searcher, board, EMERGENCY_DEPTH) {
+        Ok(Some(choice)) => (choice.view, Some(choice.score)),
+        _ => (ordered_moves(board, None)[0], None),
+    };
+    let mut stats = searcher.stats;
+    stats.time = start.elapsed();
+    SearchResult {
+        best: Some(best),
+        score,
+        stats,
+    }
+}
+
+///
+/// The share of the clock for a move with the empty squares.
+///
+fn weight(empties: u32) -> f64 {
+    if empties > 44 {
+        // The opening, where the book and the shallow search do well.
+        0.8
+    } else if empties > 16 {
+        1.3
+    } else {
+        // The search reaches the end of the game in no time.
+        0.6
+    }
+}
+
+pub struct TimeManager {
+    ///
+    /// The time reserved on every move for the network and the server.
+    ///
+    pub latency: Duration,
+}
+
+impl TimeManager {
+    ///
+    /// Returns the limits of a move on the board when our clock has `remains`.
+    ///
+    pub fn limit(&self, board: Board, remains: Duration) -> TimeLimit {
+        let empties = 64 - popcnt64!(board.player | board.opponent) as u32;
+        // Our moves including this one, ignoring passes.
+        let moves = empties.div_ceil(2).max(1);
+
+        let reserve = RESERVE.min(remains / 20);
+        let available = remains
+            .saturating_sub(reserve)
+            .saturating_sub(self.latency * moves);
+
+        let total: f64 = (0..moves)
+            .map(|i| weight(empties.saturating_sub(2 * i)))
+            .sum();
+        let soft = available.mul_f64(weight(empties) / total);
+        let hard = soft
+            .mul_f64(HARD_RATIO)
+            .min(available.mul_f64(HARD_SHARE))
+            .max(soft);
+        TimeLimit { soft, hard }
+    }
+}
+
+///
+/// Decides when to stop the search from its progress on the moves at the root.
+///
+pub struct SearchMonitor {
+    limit: TimeLimit,
+    ///
+    /// The best move of the last iteration completed by all the moves.
+    ///
+    best: Option<Choice>,
+    ///
+    /// The depth of the last iteration completed by all the moves.
+    ///
+    depth: u8,
+    ///
+    /// Whether the best move of the last iteration changed or its score dropped.
+    ///
+    extended: bool,
+    ///
+    /// Whether the best move of the last iteration is far ahead of the others.
+    ///
+    dominant: bool,
+}
+
+impl SearchMonitor {
+    pub fn new(limit: TimeLimit) -> Self {
+        SearchMonitor {
+            limit,
+            best: None,
+            depth: 0,
+            extended: false,
+            dominant: false,
+        }
+    }
+
+    ///
+    /// The time the search stops at unless something changes.
+    ///
+    pub fn stop_at(&self) -> Duration {
+        if self.extended {
+            self.limit.soft.mul_f64(EXTENSION).min(self.limit.hard)
+        } else {
+            self.limit.soft
+        }
+    }
+
+    ///
+    /// Takes the results of every move at the root by depth and returns whether to stop the search.
+    /// The moves are only compared at the depths which all of them have completed.
+    ///
+    pub fn should_stop(&mut self, elapsed: Duration, results: &[Vec<Choice>]) -> bool {
+        if elapsed >= self.limit.hard {
+            return true;
+        }
+        // A proven win needs no comparison.
+        if results
+            .iter()
+            .filter_map(|choices| choices.last())
+            .any(|choice| choice.score == INF)
+        {
+            return true;
+        }
+
+        let completed = results
+            .iter()
+            .map(|choices| choices.last().map_or(0, |choice| choice.depth))
+            .min()
+            .unwrap_or(0);
+        for depth in self.depth + 1..=completed {
+            // A move resumed from a deeper result may lack the depth.
+            let iteration: Option<Vec<&Choice>> = results
+                .iter()
+                .map(|choices| choices.iter().find(|choice| choice.depth == depth))
+                .collect();
+            if let Some(iteration) = iteration {
+                self.complete(depth, iteration);
+            }
+        }
+
+        // A dominant move stops the search early, while a fixed limit is used up.
+        if self.limit.soft < self.limit.hard && self.dominant && elapsed >= self.limit.soft / 4 {
+            return true;
+        }
+        elapsed >= self.stop_at()
+    }
+
+    ///
+    /// Records the iteration which all the moves have completed at the depth.
+    ///
+    fn complete(&mut self, depth: u8, mut iteration: Vec<&Choice>) {
+        iteration.sort_by_key(|choice| -(choice.score as i64));
+        let best = iteration[0];
+
+        // The search is unsure if the best move changes or its score drops.
+        self.extended = self.best.as_ref().is_some_and(|previous| {
+            previous.view != best.view || best.score < previous.score.saturating_sub(SCORE_DROP)
+        });
+        self.dominant = depth >= DOMINANCE_DEPTH
+            && match iteration.get(1) {
+                Some(second) => best.score.saturating_sub(second.score) >= DOMINANCE,
+                None => true,
+            };
+        self.best = Some(best.clone());
+        self.depth = depth;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::Ordering;
+
+    use crate::agent::{select_best_move, SearchOptions, DEADLINE_INTERVAL};
+    use crate::bench::bench_positions;
+    use crate::board::{get_pos, new_board};
+    use crate::proto::Color;
+
+    ///
+    /// A board with the empty squares.
+    ///
+    fn board_with(empties: u32) -> Board {
+        let mut board = new_board(&Color::Black);
+        let mut filled = 64 - 4 - empties;
+        for pos in 0..64 {
+            let view = 1u64 << pos;
+            if filled > 0 && (board.player | board.opponent) & view == 0 {
+                if pos % 2 == 0 {
+                    board.player |= view;
+                } else {
+                    board.opponent |= view;
+                }
+                filled -= 1;
+            }
+        }
+        board
+    }
+
+    ///
+    /// Plays a game whose moves take `ratio` between the soft and the hard limits
+    /// plus the latency. Returns the remaining clock, or `None` if it runs out.
+    ///
+    fn simulate(
+        clock: Duration,
+        latency: Duration,
+        book_moves: u32,
+        ratio: f64,
+    ) -> Option<Duration> {
+        let manager = TimeManager {
+            latency: Duration::from_millis(50),
+        };
+        let mut remains = clock;
+        for (i, empties) in (1..=59).rev().step_by(2).enumerate() {
+            let used = if (i as u32) < book_moves {
+                Duration::ZERO
+            } else {
+                let limit = manager.limit(board_with(empties), remains);
+                assert!(limit.soft <= limit.hard);
+                limit.soft + (limit.hard - limit.soft).mul_f64(ratio)
+            };
+            remains = remains.checked_sub(used + latency)?;
+        }
+        Some(remains)
+    }
+
+    #[test]
+    fn test_simulated_games() {
+        for secs in [2, 5, 60, 300] {
+            let clock = Duration::from_secs(secs);
+            for book_moves in [0, 6] {
+                // Every move runs to the hard limit with the latency of the margin.
+                let remains = simulate(clock, Duration::from_millis(50), book_moves, 1.0).unwrap();
+                assert!(remains > Duration::ZERO);
+
+                // Moves stopping at the soft limit use most of the clock.
+                let remains = simulate(clock, Duration::from_millis(10), book_moves, 0.0).unwrap();
+                assert!(remains < clock / 4, "{:?} of {:?} is left", remains, clock);
+            }
+        }
+
+        // A clock too short for the margins still never runs out when the moves are instant.
+        let clock = Duration::from_secs(1);
+        assert!(simulate(clock, Duration::from_millis(20), 0, 1.0).is_some());
+    }
+
+    #[test]
+    fn test_limit() {
+        let manager = TimeManager {
+            latency: Duration::from_millis(50),
+        };
+        let remains = Duration::from_secs(60);
+        let opening = manager.limit(board_with(56), remains);
+        let midgame = manager.limit(board_with(30), remains);
+        assert!(opening.soft < midgame.soft);
+        assert!(midgame.hard <= remains.mul_f64(HARD_SHARE));
+
+        // The last move may use the whole available time.
+        let last = manager.limit(board_with(1), Duration::from_millis(1000));
+        assert_eq!(last.soft, last.hard);
+        assert!(last.hard < Duration::from_millis(1000));
+
+        assert_eq!(
+            manager.limit(board_with(30), Duration::ZERO),
+            TimeLimit::fixed(Duration::ZERO)
+        );
+    }
+
+    fn choice(x: u8, depth: u8, score: i32) -> Choice {
+        Choice {
+            view: get_pos(x, 0),
+            depth,
+            score,
+            pv: vec![get_pos(x, 0)],
+        }
+    }
+
+    ///
+    /// Returns the results of the move by depth, from the scores at the depths from `first`.
+    ///
+    fn history(x: u8, first: u8, scores: &[i32]) -> Vec<Choice> {
+        scores
+            .iter()
+            .zip(first..)
+            .map(|(&score, depth)| choice(x, depth, score))
+            .collect()
+    }
+
+    #[test]
+    fn test_monitor() {
+        let limit = TimeLimit {
+            soft: Duration::from_millis(100),
+            hard: Duration::from_millis(400),
+        };
+        let ms = Duration::from_millis;
+
+        // A dominant move stops the search early.
+        let mut monitor = SearchMonitor::new(limit);
+        let dominant = [history(0, 6, &[1000]), history(1, 6, &[0])];
+        assert!(!monitor.should_stop(ms(10), &dominant));
+        assert!(monitor.should_stop(ms(30), &dominant));
+
+        // A move ahead by depth is only compared at the depth the others have completed.
+        let mut monitor = SearchMonitor::new(limit);
+        let uneven = [history(0, 6, &[10, 1000]), history(1, 6, &[0])];
+        assert!(!monitor.should_stop(ms(30), &uneven));
+
+        // The change of the best move extends the soft limit until an iteration agrees.
+        let mut monitor = SearchMonitor::new(limit);
+        let changed = [history(0, 5, &[10, 10]), history(1, 5, &[0, 20])];
+        assert!(!monitor.should_stop(ms(60), &changed));
+        assert!(!monitor.should_stop(ms(150), &changed));
+        assert!(monitor.should_stop(ms(200), &changed));
+        let settled = [history(0, 5, &[10, 10, 10]), history(1, 5, &[0, 20, 20])];
+        assert!(!monitor.should_stop(ms(60), &settled));
+        assert!(monitor.should_stop(ms(150), &settled));
+
+        // The hard limit is never exceeded.
+        let mut monitor = SearchMonitor::new(limit);
+        assert!(monitor.should_stop(ms(400), &[Vec::new(), Vec::new()]));
+
+        // A fixed limit is used up even with a dominant move.
+        let mut monitor = SearchMonitor::new(TimeLimit::fixed(ms(100)));
+        assert!(!monitor.should_stop(ms(30), &dominant));
+        assert!(monitor.should_stop(ms(100), &dominant));
+
+        // A proven win stops the search.
+        let mut monitor = SearchMonitor::new(limit);
+        let won = [history(0, 8, &[INF]), history(1, 6, &[0])];
+        assert!(monitor.should_stop(ms(1), &won));
+    }
+
+    #[test]
+    fn test_deadline() {
+        let positions = bench_positions(8, 1);
+
+        // A passed deadline interrupts the search by the next check,
+        // together with the other searches sharing the flag.
+        for &board in &positions {
+            let interrupt = Arc::new(AtomicBool::new(false));
+            let mut searcher =
+                Searcher::new(interrupt.clone(), None, None).with_deadline(Instant::now());
+            assert!(best_move_at_depth(&mut searcher, board, 30).is_err());
+            assert!(searcher.stats.nodes <= DEADLINE_INTERVAL);
+            assert!(interrupt.load(Ordering::Relaxed));
+        }
+
+        // A legal move is still chosen without any time.
+        for &board in &positions {
+            let valid = get_valid_moves(board.player, board.opponent);
+            let limit = TimeLimit::fixed(Duration::ZERO);
+            let result = select_best_move(board, limit, &SearchOptions::default(), &[]);
+            assert_ne!(result.best.unwrap() & valid, 0);
+            assert_ne!(emergency_move(board).best.unwrap() & valid, 0);
+        }
+    }
+
+    #[test]
+    fn test_response_latency() {
+        let positions = bench_positions(8, 1);
+        let limit = TimeLimit {
+            soft: Duration::from_millis(30),
+            hard: Duration::from_millis(60),
+        };
+        // The bounds are several times the limits, so a busy machine doesn't break the test,
+        // while a search ignoring its deadline still does.
+        let bound = limit.hard * 5;
+
+        // The deadline interrupts the search by itself.
+        let mut worst = Duration::ZERO;
+        for &board in &positions {
+            let start = Instant::now();
+            let mut searcher = Searcher::new(Arc::new(AtomicBool::new(false)), None, None)
+                .with_deadline(start + limit.hard);
+            assert!(best_move_at_depth(&mut searcher, board, 30).is_err());
+            worst = worst.max(start.elapsed());
+        }
+        assert!(worst < bound, "{:?}", worst);
+
+        let mut worst = Duration::ZERO;
+        for &board in &positions {
+            let start = Instant::now();
+            let result = select_best_move(board, limit, &SearchOptions::default(), &[]);
+            worst = worst.max(start.elapsed());
+            assert!(result.best.unwrap() & get_valid_moves(board.player, board.opponent) != 0);
+        }
+        assert!(worst < bound, "{:?}", worst);
+
+        let mut worst = Duration::ZERO;
+        for &board in &positions {
+            let start = Instant::now();
+            let result = emergency_move(board);
+            worst = worst.max(start.elapsed());
+            assert!(result.best.unwrap() & get_valid_moves(board.player, board.opponent) != 0);
+        }
+        assert!(worst < EMERGENCY_LIMIT * 5, "{:?}", worst);
+    }
+}
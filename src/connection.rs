@@ -1,98 +1,432 @@
 use std::{
-    collections::HashMap,
-    io::{BufRead, BufReader, BufWriter, Write},
-    net::TcpStream,
-    time::Duration,
+    io,
+    sync::{atomic::Ordering, Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter, Lines},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream,
+    },
+    task,
 };
 
 use crate::{
-    agent::select_best_move,
-    board::{from_pos, get_pos, new_board, put, Board},
+    agent::{create_agent, Agent, SearchResult},
+    board::{
+        from_notation, get_pos, get_valid_moves, new_board, put, to_notation, Board, BoardView,
+        PASS,
+    },
+    book::{load_book, OpeningBook},
+    clock::{emergency_move, TimeLimit, TimeManager},
+    ggf::{save_game, GameMove, SavedGame},
     parser::parse_request,
-    popcnt64, print_board,
+    print_board,
     proto::{Color, Error, Request},
+    util::Rng,
     write_log, Args,
 };
 
+///
+/// The connection to the server, exchanging one message per line.
+///
+pub struct Connection {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: BufWriter<OwnedWriteHalf>,
+}
+
+impl Connection {
+    pub async fn connect(host: &str, port: u16) -> Result<Self, Error> {
+        let stream = TcpStream::connect((host, port)).await?;
+        let (reader, writer) = stream.into_split();
+        Ok(Connection {
+            lines: BufReader::new(reader).lines(),
+            writer: BufWriter::new(writer),
+        })
+    }
+
+    ///
+    /// Sends the message followed by a newline.
+    ///
+    pub async fn send(&mut self, message: &str) -> Result<(), Error> {
+        self.writer.write_all(message.as_bytes()).await?;
+        self.writer.write_all(b"\n").await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    ///
+    /// Receives a message without the newline.
+    /// It is cancel safe, so that it can be raced against the search.
+    ///
+    pub async fn receive(&mut self) -> Result<String, Error> {
+        match self.lines.next_line().await? {
+            Some(line) => Ok(line),
+            None => Err(Error::IO(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the server closed the connection",
+            ))),
+        }
+    }
+}
+
+///
+/// Returns whether the message ends the game, after which our move is of no use.
+///
+fn ends_game(message: &str) -> bool {
+    matches!(
+        parse_request(message),
+        Ok(Request::End { .. } | Request::Bye { .. })
+    )
+}
+
+///
+/// Runs the search on a blocking thread while listening to the server.
+/// A message ending the game aborts the search and is returned as `Err`. The others, which the
+/// server should not send on our turn, are logged and ignored so that we still move in time.
+///
+async fn search(
+    agent: &Arc<Mutex<Box<dyn Agent>>>,
+    board: Board,
+    limit: TimeLimit,
+    connection: &mut Connection,
+) -> Result<Result<SearchResult, String>, Error> {
+    let abort = agent.lock().unwrap().abort_handle();
+    if let Some(abort) = &abort {
+        abort.store(false, Ordering::Relaxed);
+    }
+
+    let searching = agent.clone();
+    let mut task =
+        task::spawn_blocking(move || searching.lock().unwrap().choose_move(board, limit));
+    loop {
+        tokio::select! {
+            result = &mut task => return Ok(Ok(result.map_err(io::Error::other)?)),
+            message = connection.receive() => {
+                if let Ok(message) = &message {
+                    if !ends_game(message) {
+                        write_log!(WARN, "Ignored \"{}\" during the search.", message);
+                        continue;
+                    }
+                }
+                write_log!(DEBUG, "The game ended during the search. Aborting it.");
+                if let Some(abort) = &abort {
+                    abort.store(true, Ordering::Relaxed);
+                }
+                task.await.map_err(io::Error::other)?;
+                return Ok(Err(message?));
+            }
+        }
+    }
+}
+
+///
+/// What happened on our turn.
+///
+pub enum Turn {
+    Moved(GameMove),
+    ///
+    /// The message ending the game arrived before the move was decided, which is left unplayed.
+    ///
+    Interrupted(String),
+}
+
 pub async fn do_move(
     board: &mut Board,
     me: &Color,
     remains: u64,
-    writer: &mut BufWriter<&TcpStream>,
+    connection: &mut Connection,
     history: &mut String,
-) -> Result<(), Error> {
-    let usable = if remains < 8000 {
-        let space = 64 - (popcnt64!(board.player) + popcnt64!(board.opponent));
-        if space == 0 {
-            remains
-        } else {
-            (remains / space as u64) << 1
-        }
+    agent: &Arc<Mutex<Box<dyn Agent>>>,
+    args: &Args,
+) -> Result<Turn, Error> {
+    let start = Instant::now();
+    let manager = TimeManager {
+        latency: Duration::from_millis(args.latency_margin),
+    };
+    let limit = manager.limit(*board, Duration::from_millis(remains));
+    write_log!(
+        DEBUG,
+        "Time limit: soft = {}ms, hard = {}ms of {}ms",
+        limit.soft.as_millis(),
+        limit.hard.as_millis(),
+        remains
+    );
+
+    let result = if limit.is_emergency() {
+        write_log!(WARN, "Only {}ms remains. Playing an instant move.", remains);
+        emergency_move(*board)
     } else {
-        2200
+        match search(agent, *board, limit, connection).await? {
+            Ok(result) => result,
+            Err(message) => return Ok(Turn::Interrupted(message)),
+        }
     };
+    if let Some(path) = &args.stats {
+        result
+            .stats
+            .append_csv(path, &to_notation(result.best.unwrap_or(PASS)))?;
+    }
 
-    match select_best_move(*board, Duration::from_millis(usable)).await {
+    match result.best {
         Some(view) => {
             put(view, &mut board.player, &mut board.opponent);
-            let (x, y) = from_pos(view);
-            writer.write(format!("MOVE {}{}\n", (b'A' + x) as char, y + 1).as_bytes())?;
-            *history += &format!("{}{}", (b'A' + x) as char, y + 1);
+            let notation = to_notation(view);
+            connection.send(&format!("MOVE {}", notation)).await?;
+            *history += &notation;
 
-            write_log!(LOG, "ME {}{}", (b'A' + x) as char, y + 1);
+            write_log!(LOG, "ME {}", notation);
             print_board!(LOG, board, &me);
         }
         None => {
-            writer.write(b"MOVE PASS\n")?;
+            connection.send("MOVE PASS").await?;
         }
     }
-    writer.flush()?;
-    Ok(())
+
+    agent.lock().unwrap().ponder(*board);
+    Ok(Turn::Moved(GameMove {
+        color: *me,
+        view: result.best.unwrap_or(PASS),
+        time: start.elapsed(),
+        score: result.score.map(GameMove::score_in_discs),
+    }))
+}
+
+///
+/// Returns a move from the book if it is legal on the board.
+/// An illegal one, which comes from a corrupted book, is reported and left to the search.
+///
+fn book_move(
+    book: &dyn OpeningBook,
+    board: Board,
+    args: &Args,
+    rng: &mut Rng,
+) -> Option<BoardView> {
+    let mv = book.choose_move(board, args.book_margin, rng)?;
+    if mv.view & get_valid_moves(board.player, board.opponent) == 0 {
+        write_log!(
+            WARN,
+            "The book move {} is illegal on the board. Searching instead.",
+            to_notation(mv.view)
+        );
+        return None;
+    }
+    Some(mv.view)
+}
+
+fn opponent_of(color: Color) -> Color {
+    match color {
+        Color::Black => Color::White,
+        Color::White => Color::Black,
+    }
 }
 
-static PREPROCESSED_FILE: &str = "./preprocessed.txt";
+///
+/// Appends a move without a score to the record of the game.
+///
+fn push_move(game: &mut Option<SavedGame>, color: Color, view: BoardView, time: Duration) {
+    if let Some(game) = game {
+        game.moves.push(GameMove {
+            color,
+            view,
+            time,
+            score: None,
+        });
+    }
+}
+
+///
+/// Records our move, or keeps the message which ended the game during the search to handle it next.
+///
+fn record_turn(turn: Turn, game: &mut Option<SavedGame>, pending: &mut Option<String>) {
+    match turn {
+        Turn::Moved(mv) => {
+            if let Some(game) = game {
+                game.moves.push(mv);
+            }
+        }
+        Turn::Interrupted(message) => {
+            write_log!(WARN, "The search was interrupted by \"{}\".", message);
+            *pending = Some(message);
+        }
+    }
+}
 
-pub fn load_preprocessed() -> Result<HashMap<String, String>, Error> {
-    let text = std::fs::read_to_string(PREPROCESSED_FILE)?;
+///
+/// The state of the client kept across the sessions and the reconnections.
+///
+struct Client {
+    book: Box<dyn OpeningBook>,
+    ///
+    /// Shared with the thread of the search.
+    ///
+    agent: Arc<Mutex<Box<dyn Agent>>>,
+    rng: Rng,
+    ///
+    /// `None` lets the book choose the first move.
+    ///
+    first_move: Option<BoardView>,
+}
 
-    let mut table = HashMap::new();
-    for line in text.lines() {
-        let mut iter = line.split_whitespace();
-        let key = iter.next().ok_or(Error::Parser)?;
-        let value = iter.next().ok_or(Error::Parser)?;
-        table.insert(key.to_string(), value.to_string());
+impl Client {
+    fn new(args: &Args) -> Result<Self, Error> {
+        let first_move = if args.first_move.eq_ignore_ascii_case("book") {
+            None
+        } else {
+            let initial = new_board(&Color::Black);
+            let view = from_notation(&args.first_move)
+                .filter(|&view| view & get_valid_moves(initial.player, initial.opponent) != 0)
+                .ok_or(Error::ParserWithMessage(args.first_move.clone()))?;
+            Some(view)
+        };
+        write_log!(DEBUG, "Loading the opening book.");
+        Ok(Client {
+            book: load_book(args),
+            agent: Arc::new(Mutex::new(create_agent(args)?)),
+            rng: Rng::new(args.seed),
+            first_move,
+        })
     }
+}
+
+///
+/// The delay before the first reconnection, which doubles on every failure in a row.
+///
+const RETRY_DELAY: Duration = Duration::from_millis(500);
 
-    Ok(table)
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+///
+/// Returns the delay before the reconnection after `failures` failures in a row.
+///
+fn backoff(failures: u32) -> Duration {
+    RETRY_DELAY
+        .saturating_mul(1 << failures.saturating_sub(1).min(16))
+        .min(MAX_RETRY_DELAY)
+}
+
+///
+/// Returns whether connecting again may recover from the error.
+/// A message which cannot be parsed only ends the session before a game starts, when it means
+/// that the server rejected `OPEN` or speaks another protocol, so connecting again never helps.
+///
+fn is_transient(error: &Error) -> bool {
+    match error {
+        Error::IO(error) => matches!(
+            error.kind(),
+            io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::NotConnected
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::TimedOut
+                | io::ErrorKind::UnexpectedEof
+                | io::ErrorKind::Interrupted
+        ),
+        Error::Parser | Error::ParserWithMessage(_) | Error::Failed(_) => false,
+    }
 }
 
+///
+/// Plays sessions until `--sessions` of them end with `BYE`.
+/// Transient errors are retried up to `--retries` times in a row with exponential backoff.
+///
 pub async fn play_game(args: &Args) -> Result<(), Error> {
-    write_log!(DEBUG, "Loading a preprocessed table.");
-    let table = load_preprocessed()?;
+    let mut client = Client::new(args)?;
+    let mut sessions = 0;
+    let mut failures = 0;
+    loop {
+        let mut started = false;
+        let result = play_session(args, &mut client, &mut started).await;
+        client.agent.lock().unwrap().stop_pondering();
+        match result {
+            Ok(()) => {
+                sessions += 1;
+                if sessions == args.sessions {
+                    return Ok(());
+                }
+                failures = 0;
+                write_log!(LOG, "Starting the session {}.", sessions + 1);
+            }
+            Err(error) => {
+                if !is_transient(&error) {
+                    return Err(error);
+                }
+                if started {
+                    failures = 0;
+                }
+                failures += 1;
+                if failures > args.retries {
+                    return Err(error);
+                }
+                let delay = backoff(failures);
+                write_log!(
+                    WARN,
+                    "Lost the connection: {:?}. Reconnecting in {}ms ({}/{}).",
+                    error,
+                    delay.as_millis(),
+                    failures,
+                    args.retries
+                );
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
 
-    let addr = format!("{}:{}", args.host, args.port);
-    let stream = TcpStream::connect(addr)?;
-    let mut reader = BufReader::new(&stream);
-    let mut writer = BufWriter::new(&stream);
+///
+/// Connects to the server and plays the games until `BYE`.
+/// `started` is set once a game starts.
+///
+async fn play_session(args: &Args, client: &mut Client, started: &mut bool) -> Result<(), Error> {
+    let Client {
+        book,
+        agent,
+        rng,
+        first_move,
+    } = client;
+    let mut connection = Connection::connect(&args.host, args.port).await?;
 
     write_log!(DEBUG, "Connected to the server.");
 
     let mut board = new_board(&Color::Black);
     let mut me = Color::Black;
 
-    writer.write(format!("OPEN {}\n", args.name).as_bytes())?;
-    writer.flush()?;
+    connection.send(&format!("OPEN {}", args.name)).await?;
 
     write_log!(DEBUG, "Sent OPEN");
 
     let mut time_remains = 0;
     let mut history = String::new();
+    let mut game: Option<SavedGame> = None;
+    // When the opponent started to think.
+    let mut last_sent = Instant::now();
+    // A message which arrived during the search.
+    let mut pending = None;
 
     loop {
-        let mut buf = String::new();
-        reader.read_line(&mut buf)?;
+        let buf = match pending.take() {
+            Some(message) => message,
+            None => connection.receive().await?,
+        };
+        let received = Instant::now();
 
-        let req = parse_request(&buf).map_err(|_| Error::ParserWithMessage(buf))?;
+        let req = match parse_request(&buf) {
+            Ok(req) => req,
+            // It arrived on the opponent's turn, as the search ignores it on ours,
+            // so we keep waiting for the move of the opponent.
+            Err(_) if *started => {
+                write_log!(WARN, "Skipped an unexpected message \"{}\".", buf);
+                continue;
+            }
+            Err(_) => return Err(Error::ParserWithMessage(buf)),
+        };
+        // `ACK` follows our move at once, while the opponent is still thinking.
+        if !matches!(req, Request::Ack { .. }) {
+            agent.lock().unwrap().stop_pondering();
+        }
 
         match req {
             Request::Start {
@@ -108,23 +442,57 @@ pub async fn play_game(args: &Args) -> Result<(), Error> {
                     remains
                 );
 
+                *started = true;
                 me = color;
                 board = new_board(&me);
+                agent.lock().unwrap().new_game(&me);
                 time_remains = remains;
                 history = String::new();
+                let (black, white) = match me {
+                    Color::Black => (args.name.clone(), opponent),
+                    Color::White => (opponent, args.name.clone()),
+                };
+                game = Some(SavedGame {
+                    date: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |date| date.as_secs()),
+                    black,
+                    white,
+                    time: remains,
+                    moves: Vec::new(),
+                    result: 0,
+                    reason: String::new(),
+                });
+                last_sent = received;
 
-                match &me {
-                    Color::Black => {
-                        // Fix the first move.
-                        put(get_pos(2, 3), &mut board.player, &mut board.opponent);
-                        writer.write("MOVE C4\n".as_bytes())?;
-                        writer.flush()?;
-                        history += "C4";
-                    }
-                    _ => {}
+                if let Color::Black = &me {
+                    let view = match *first_move {
+                        Some(view) => view,
+                        None => match book_move(book.as_ref(), board, args, rng) {
+                            Some(view) => view,
+                            // Every first move is equivalent.
+                            None => rng.pick(get_valid_moves(board.player, board.opponent)),
+                        },
+                    };
+                    let notation = to_notation(view);
+                    put(view, &mut board.player, &mut board.opponent);
+                    connection.send(&format!("MOVE {}", notation)).await?;
+                    history += &notation;
+                    push_move(&mut game, me, view, received.elapsed());
+                    last_sent = Instant::now();
+
+                    write_log!(LOG, "ME {}", notation);
+                    agent.lock().unwrap().ponder(board);
                 }
             }
             Request::Move { x, y } => {
+                push_move(
+                    &mut game,
+                    opponent_of(me),
+                    get_pos(x, y),
+                    received - last_sent,
+                );
+                agent.lock().unwrap().observe(board, get_pos(x, y));
                 put(get_pos(x, y), &mut board.opponent, &mut board.player);
                 history += &format!("{}{}", (b'A' + x) as char, y + 1);
 
@@ -132,29 +500,52 @@ pub async fn play_game(args: &Args) -> Result<(), Error> {
                 print_board!(LOG, board, &me);
 
                 write_log!(DEBUG, "History: {}", history);
-                if let Some(best_move) = table.get(&history) {
-                    write_log!(DEBUG, "Preprocessed move: {}", best_move);
+                if let Some(view) = book_move(book.as_ref(), board, args, rng) {
+                    let best_move = to_notation(view);
+                    write_log!(DEBUG, "Book move: {}", best_move);
+
+                    connection.send(&format!("MOVE {}", best_move)).await?;
 
-                    writer.write(format!("MOVE {}\n", best_move).as_bytes())?;
-                    writer.flush()?;
+                    history += &best_move;
+                    put(view, &mut board.player, &mut board.opponent);
+                    push_move(&mut game, me, view, received.elapsed());
 
                     write_log!(LOG, "ME {}", best_move);
                     print_board!(LOG, board, &me);
 
-                    history += best_move;
-
-                    let mut best_move = best_move.chars();
-                    let x = best_move.next().ok_or(Error::Parser)? as u8 - 'A' as u8;
-                    let y = best_move.next().ok_or(Error::Parser)? as u8 - '1' as u8;
-                    put(get_pos(x, y), &mut board.player, &mut board.opponent);
+                    agent.lock().unwrap().ponder(board);
                 } else {
-                    do_move(&mut board, &me, time_remains, &mut writer, &mut history).await?;
+                    let turn = do_move(
+                        &mut board,
+                        &me,
+                        time_remains,
+                        &mut connection,
+                        &mut history,
+                        agent,
+                        args,
+                    )
+                    .await?;
+                    record_turn(turn, &mut game, &mut pending);
                 }
+                last_sent = Instant::now();
             }
             Request::Pass => {
                 write_log!(LOG, "OPPONENT PASS");
+                push_move(&mut game, opponent_of(me), PASS, received - last_sent);
+                agent.lock().unwrap().observe(board, PASS);
 
-                do_move(&mut board, &me, time_remains, &mut writer, &mut history).await?;
+                let turn = do_move(
+                    &mut board,
+                    &me,
+                    time_remains,
+                    &mut connection,
+                    &mut history,
+                    agent,
+                    args,
+                )
+                .await?;
+                record_turn(turn, &mut game, &mut pending);
+                last_sent = Instant::now();
             }
             Request::GiveUp => {
                 write_log!(LOG, "OPPONENT GIVEUP");
@@ -169,6 +560,18 @@ pub async fn play_game(args: &Args) -> Result<(), Error> {
                 write_log!(LOG, "- result: {}", result);
                 write_log!(LOG, "- score me/opponent: {}/{}", score, opponent_score);
                 write_log!(LOG, "- reason: {}", reason);
+                agent.lock().unwrap().game_end(&result);
+
+                if let Some(mut game) = game.take().filter(|_| !args.no_record) {
+                    game.result = SavedGame::black_result(me, result, score, opponent_score);
+                    game.reason = reason;
+                    match save_game(&args.record_dir, &game) {
+                        Ok(path) => write_log!(DEBUG, "Saved the game to {}.", path.display()),
+                        Err(_) => {
+                            write_log!(WARN, "Failed to save the game to {}.", args.record_dir)
+                        }
+                    }
+                }
             }
             Request::Bye { stats } => {
                 for stat in stats {
@@ -187,3 +590,286 @@ pub async fn play_game(args: &Args) -> Result<(), Error> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::AtomicBool;
+
+    use clap::Parser;
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::agent::{AlphaBetaAgent, SearchOptions};
+
+    fn client_args(port: u16) -> Args {
+        Args::parse_from([
+            "rinee",
+            "--port",
+            &port.to_string(),
+            "--engine",
+            "random",
+            "--no-book",
+            "--no-record",
+            "--retries",
+            "2",
+        ])
+    }
+
+    ///
+    /// Accepts the connections in turn, answering `OPEN` with the replies and closing them.
+    /// Returns the names sent with `OPEN`.
+    ///
+    async fn serve(listener: TcpListener, replies: &[&str]) -> Vec<String> {
+        let mut names = Vec::new();
+        for reply in replies {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let line = BufReader::new(reader).lines().next_line().await.unwrap();
+            names.push(line.unwrap().trim_start_matches("OPEN ").to_string());
+            writer
+                .write_all(format!("{}\n", reply).as_bytes())
+                .await
+                .unwrap();
+        }
+        names
+    }
+
+    ///
+    /// Searches without time, so that only the pondered results give a depth, and records it.
+    ///
+    struct NoTimeAgent {
+        inner: AlphaBetaAgent,
+        depths: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl Agent for NoTimeAgent {
+        fn new_game(&mut self, me: &Color) {
+            self.inner.new_game(me);
+        }
+
+        fn choose_move(&mut self, board: Board, _limit: TimeLimit) -> SearchResult {
+            let result = self
+                .inner
+                .choose_move(board, TimeLimit::fixed(Duration::ZERO));
+            self.depths.lock().unwrap().push(result.stats.depth);
+            result
+        }
+
+        fn observe(&mut self, board: Board, view: BoardView) {
+            self.inner.observe(board, view);
+        }
+
+        fn ponder(&mut self, board: Board) {
+            self.inner.ponder(board);
+        }
+
+        fn stop_pondering(&mut self) {
+            self.inner.stop_pondering();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ponder_through_ack() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let args = client_args(listener.local_addr().unwrap().port());
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, writer) = stream.into_split();
+            let mut connection = Connection {
+                lines: BufReader::new(reader).lines(),
+                writer: BufWriter::new(writer),
+            };
+            assert_eq!(connection.receive().await.unwrap(), "OPEN anonymous");
+            connection.send("START BLACK opponent 60000").await.unwrap();
+            assert_eq!(connection.receive().await.unwrap(), "MOVE C4");
+            // The opponent thinks after the acknowledgement.
+            connection.send("ACK 59990").await.unwrap();
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            connection.send("MOVE C3").await.unwrap();
+            let reply = connection.receive().await.unwrap();
+            connection.send("END WIN 5 1 TEST").await.unwrap();
+            connection.send("BYE anonymous 4 1 0").await.unwrap();
+            reply
+        });
+
+        let depths = Arc::new(Mutex::new(Vec::new()));
+        let mut client = Client::new(&args).unwrap();
+        client.agent = Arc::new(Mutex::new(Box::new(NoTimeAgent {
+            inner: AlphaBetaAgent::new(SearchOptions::default(), true),
+            depths: depths.clone(),
+        })));
+        let mut started = false;
+        play_session(&args, &mut client, &mut started)
+            .await
+            .unwrap();
+
+        assert!(server.await.unwrap().starts_with("MOVE "));
+        let depths = depths.lock().unwrap();
+        assert_eq!(depths.len(), 1);
+        assert!(depths[0] >= 5, "depth = {}", depths[0]);
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), RETRY_DELAY);
+        assert_eq!(backoff(3), RETRY_DELAY * 4);
+        assert_eq!(backoff(100), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_is_transient() {
+        let dropped = Error::IO(io::Error::from(io::ErrorKind::ConnectionReset));
+        assert!(is_transient(&dropped));
+        let denied = Error::IO(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert!(!is_transient(&denied));
+        let garbled = Error::ParserWithMessage(String::from("ERROR"));
+        assert!(!is_transient(&garbled));
+    }
+
+    #[tokio::test]
+    async fn test_garbled_during_search() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut args = client_args(listener.local_addr().unwrap().port());
+        args.engine = crate::Engine::AlphaBeta;
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, writer) = stream.into_split();
+            let mut connection = Connection {
+                lines: BufReader::new(reader).lines(),
+                writer: BufWriter::new(writer),
+            };
+            connection.receive().await.unwrap();
+            connection.send("START WHITE opponent 5000").await.unwrap();
+            connection.send("MOVE F5").await.unwrap();
+            // It arrives while the client searches its reply.
+            connection.send("GARBLED").await.unwrap();
+            let reply = connection.receive().await.unwrap();
+            connection.send("END WIN 5 1 TEST").await.unwrap();
+            connection.send("BYE anonymous 4 1 0").await.unwrap();
+            reply
+        });
+
+        let mut client = Client::new(&args).unwrap();
+        let mut started = false;
+        let session = play_session(&args, &mut client, &mut started);
+        tokio::time::timeout(Duration::from_secs(30), session)
+            .await
+            .expect("the client moves")
+            .unwrap();
+        let reply = server.await.unwrap();
+        assert!(
+            ["MOVE D6", "MOVE F6", "MOVE F4"].contains(&reply.as_str()),
+            "{}",
+            reply
+        );
+    }
+
+    #[tokio::test]
+    async fn test_skip_garbled() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let args = client_args(listener.local_addr().unwrap().port());
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, writer) = stream.into_split();
+            let mut connection = Connection {
+                lines: BufReader::new(reader).lines(),
+                writer: BufWriter::new(writer),
+            };
+            connection.receive().await.unwrap();
+            connection.send("START WHITE opponent 60000").await.unwrap();
+            connection.send("GARBLED").await.unwrap();
+            connection.send("BYE anonymous 0 0 0").await.unwrap();
+        });
+
+        // A garbled message on the opponent's turn is skipped instead of ending the session.
+        let mut client = Client::new(&args).unwrap();
+        let mut started = false;
+        let result = play_session(&args, &mut client, &mut started).await;
+        assert!(result.is_ok());
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let args = client_args(listener.local_addr().unwrap().port());
+        // The first connection drops in the middle of a game.
+        let server = tokio::spawn(async move {
+            serve(
+                listener,
+                &["START WHITE opponent 60000", "BYE anonymous 0 0 0"],
+            )
+            .await
+        });
+
+        let result = tokio::time::timeout(Duration::from_secs(10), play_game(&args)).await;
+        assert!(result.unwrap().is_ok());
+        assert_eq!(server.await.unwrap(), ["anonymous", "anonymous"]);
+    }
+
+    #[tokio::test]
+    async fn test_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let args = client_args(listener.local_addr().unwrap().port());
+        let server = tokio::spawn(async move { serve(listener, &["ERROR bad name"]).await });
+
+        let result = tokio::time::timeout(Duration::from_secs(10), play_game(&args)).await;
+        assert!(matches!(result.unwrap(), Err(Error::ParserWithMessage(_))));
+        assert_eq!(server.await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_search_aborted_by_message() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // The server sends the lines when told to.
+        let (send_line, mut lines) = tokio::sync::mpsc::unbounded_channel::<&str>();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            while let Some(line) = lines.recv().await {
+                stream
+                    .write_all(format!("{}\n", line).as_bytes())
+                    .await
+                    .unwrap();
+            }
+            stream
+        });
+
+        let mut connection = Connection::connect("127.0.0.1", port).await.unwrap();
+        let options = SearchOptions {
+            abort: Some(Arc::new(AtomicBool::new(false))),
+            ..Default::default()
+        };
+        let agent: Box<dyn Agent> = Box::new(AlphaBetaAgent::new(options, false));
+        let agent = Arc::new(Mutex::new(agent));
+        let board = new_board(&Color::Black);
+
+        // The search completes while the server is silent.
+        let limit = TimeLimit::fixed(Duration::from_millis(50));
+        let result = search(&agent, board, limit, &mut connection).await.unwrap();
+        assert!(result.unwrap().best.is_some());
+
+        // A message not ending the game is ignored, and the search completes.
+        send_line.send("ACK 1000").unwrap();
+        let limit = TimeLimit::fixed(Duration::from_millis(200));
+        let result = search(&agent, board, limit, &mut connection).await.unwrap();
+        assert!(result.unwrap().best.is_some());
+
+        // The end of the game aborts the search, which would run for an hour otherwise.
+        send_line.send("END LOSE 10 54 TIMEOUT").unwrap();
+        let limit = TimeLimit::fixed(Duration::from_secs(3600));
+        let result = tokio::time::timeout(
+            Duration::from_secs(60),
+            search(&agent, board, limit, &mut connection),
+        )
+        .await
+        .expect("the search is aborted");
+        assert_eq!(
+            result.unwrap().err().as_deref(),
+            Some("END LOSE 10 54 TIMEOUT")
+        );
+
+        drop(send_line);
+        server.await.unwrap();
+    }
+}
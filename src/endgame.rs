@@ -0,0 +1,270 @@
+//
+// An exact endgame solver.
+// Unlike `Searcher`, which only tells a win from a loss at the end of the game,
+// it gives the final disc differential with the empty squares counted for the winner.
+//
+
+use crate::{
+    board::{get_valid_moves, put, Board, BoardView},
+    popcnt64,
+    tt::{Bound, Entry, TranspositionTable},
+    tzcnt64,
+};
+
+///
+/// Nodes with fewer empty squares are searched without ordering the moves or the table.
+///
+const SHALLOW_EMPTIES: u32 = 7;
+
+///
+/// Returns the disc differential for the player at the end of the game.
+///
+pub fn final_score(board: Board) -> i32 {
+    let player = popcnt64!(board.player) as i32;
+    let opponent = popcnt64!(board.opponent) as i32;
+    let empty = 64 - player - opponent;
+    if player > opponent {
+        player - opponent + empty
+    } else if player < opponent {
+        player - opponent - empty
+    } else {
+        0
+    }
+}
+
+fn swap(board: Board) -> Board {
+    Board {
+        player: board.opponent,
+        opponent: board.player,
+    }
+}
+
+///
+/// Plays the move and returns the board from the viewpoint of the opponent.
+///
+fn play(board: Board, view: BoardView) -> Board {
+    let mut board = board;
+    put(view, &mut board.player, &mut board.opponent);
+    swap(board)
+}
+
+///
+/// Returns the legal moves, `first` first and then the ones leaving the opponent the fewest moves.
+///
+pub fn ordered_moves(board: Board, first: Option<BoardView>) -> Vec<BoardView> {
+    let mut valid = get_valid_moves(board.player, board.opponent);
+    let mut moves = Vec::with_capacity(popcnt64!(valid) as usize);
+    while valid != 0 {
+        let view = 1 << tzcnt64!(valid);
+        valid ^= view;
+        let next = play(board, view);
+        let mut key = popcnt64!(get_valid_moves(next.player, next.opponent)) * 4;
+        if view & 0x8100000000000081 != 0 {
+            key -= 2;
+        }
+        if Some(view) == first {
+            key = i32::MIN;
+        }
+        moves.push((key, view));
+    }
+    moves.sort_by_key(|&(key, _)| key);
+    moves.into_iter().map(|(_, view)| view).collect()
+}
+
+pub struct Solver {
+    tt: TranspositionTable,
+    pub nodes: u64,
+}
+
+impl Solver {
+    ///
+    /// Creates a solver with a table of `2^bits` entries.
+    ///
+    pub fn new(bits: u32) -> Self {
+        Solver {
+            tt: TranspositionTable::new(bits),
+            nodes: 0,
+        }
+    }
+
+    ///
+    /// Solves the board for the side to move and returns the score with the best move.
+    /// The move is `None` if the side to move has to pass.
+    ///
+    pub fn solve(&mut self, board: Board) -> (i32, Option<BoardView>) {
+        self.nodes += 1;
+        if get_valid_moves(board.player, board.opponent) == 0 {
+            return if get_valid_moves(board.opponent, board.player) == 0 {
+                (final_score(board), None)
+            } else {
+                (-self.search(swap(board), -64, 64), None)
+            };
+        }
+
+        let mut alpha = -65;
+        let mut best = None;
+        for (i, view) in ordered_moves(board, None).into_iter().enumerate() {
+            let next = play(board, view);
+            let score = if i == 0 {
+                -self.search(next, -64, 64)
+            } else {
+                let score = -self.search(next, -alpha - 1, -alpha);
+                if score > alpha {
+                    -self.search(next, -64, -alpha)
+                } else {
+                    score
+                }
+            };
+            if score > alpha {
+                alpha = score;
+                best = Some(view);
+            }
+        }
+        (alpha, best)
+    }
+
+    ///
+    /// The fail-hard principal variation search returning the score within `alpha..=beta`.
+    ///
+    fn search(&mut self, board: Board, alpha: i32, beta: i32) -> i32 {
+        let empties = 64 - popcnt64!(board.player | board.opponent) as u32;
+        if empties < SHALLOW_EMPTIES {
+            return self.shallow(board, alpha, beta, false);
+        }
+        self.nodes += 1;
+
+        if get_valid_moves(board.player, board.opponent) == 0 {
+            if get_valid_moves(board.opponent, board.player) == 0 {
+                return final_score(board).clamp(alpha, beta);
+            }
+            return -self.search(swap(board), -beta, -alpha);
+        }
+
+        let mut first = None;
+        if let Some(entry) = self.tt.probe(board, true) {
+            match entry.bound {
+                Bound::Exact => return entry.score.clamp(alpha, beta),
+                Bound::Lower if entry.score >= beta => return beta,
+                Bound::Upper if entry.score <= alpha => return alpha,
+                _ => {}
+            }
+            first = entry.best;
+        }
+
+        let original = alpha;
+        let mut alpha = alpha;
+        let mut best = None;
+        for (i, view) in ordered_moves(board, first).into_iter().enumerate() {
+            let next = play(board, view);
+            let score = if i == 0 {
+                -self.search(next, -beta, -alpha)
+            } else {
+                let score = -self.search(next, -alpha - 1, -alpha);
+                if score > alpha && score < beta {
+                    -self.search(next, -beta, -alpha)
+                } else {
+                    score
+                }
+            };
+            if score > alpha {
+                alpha = score;
+                best = Some(view);
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+
+        let bound = if alpha <= original {
+            Bound::Upper
+        } else if alpha >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.tt.store(
+            board,
+            true,
+            Entry {
+                score: alpha,
+                depth: empties as u8,
+                bound,
+                best,
+            },
+        );
+        alpha
+    }
+
+    ///
+    /// The plain fail-hard alpha-beta search near the end of the game.
+    ///
+    fn shallow(&mut self, board: Board, alpha: i32, beta: i32, passed: bool) -> i32 {
+        self.nodes += 1;
+        let mut valid = get_valid_moves(board.player, board.opponent);
+        if valid == 0 {
+            if passed {
+                return final_score(board).clamp(alpha, beta);
+            }
+            return -self.shallow(swap(board), -beta, -alpha, true);
+        }
+
+        let mut alpha = alpha;
+        while valid != 0 {
+            let view = 1 << tzcnt64!(valid);
+            valid ^= view;
+            let score = -self.shallow(play(board, view), -beta, -alpha, false);
+            if score > alpha {
+                alpha = score;
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+        alpha
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{board::new_board, proto::Color, util::Rng};
+
+    fn minimax(board: Board, passed: bool) -> i32 {
+        let mut valid = get_valid_moves(board.player, board.opponent);
+        if valid == 0 {
+            if passed {
+                return final_score(board);
+            }
+            return -minimax(swap(board), true);
+        }
+        let mut best = -64;
+        while valid != 0 {
+            let view = 1 << tzcnt64!(valid);
+            valid ^= view;
+            best = best.max(-minimax(play(board, view), false));
+        }
+        best
+    }
+
+    #[test]
+    fn test_solve() {
+        let mut rng = Rng::new(1);
+        for _ in 0..20 {
+            let mut board = new_board(&Color::Black);
+            while 64 - popcnt64!(board.player | board.opponent) > 10 {
+                let valid = get_valid_moves(board.player, board.opponent);
+                board = if valid == 0 {
+                    swap(board)
+                } else {
+                    play(board, rng.pick(valid))
+                };
+            }
+            let mut solver = Solver::new(16);
+            let (score, best) = solver.solve(board);
+            assert_eq!(score, minimax(board, false));
+            if let Some(best) = best {
+                assert_eq!(-minimax(play(board, best), false), score);
+            }
+        }
+    }
+}
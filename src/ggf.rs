@@ -0,0 +1,552 @@
+//
+// Full records of the games played by the client in the GGF (Generic Game Format), one game per file.
+//
+// Besides the standard tags, a record keeps the reason of the end given by the server in `C[]`.
+// The result `RE[]` is the disc differential for black, or 64 discs for the winner when the game
+// is decided by a violation, which is marked by `:t` (timeout) or `:r` (others) as on GGS.
+//
+// References:
+//  https://skatgame.net/mburo/ggsa/ggf
+//
+
+use std::{
+    fs::OpenOptions,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    agent::VALUE_PER_DISC,
+    board::{from_notation, new_board, parse_history, put, to_notation, BoardView, PASS},
+    popcnt64,
+    proto::{Color, Error, GameResult},
+    write_log,
+};
+
+pub static DEFAULT_RECORD_DIR: &str = "records";
+
+const INITIAL_BOARD: &str = "8 ---------------------------O*------*O--------------------------- *";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameMove {
+    pub color: Color,
+    ///
+    /// `PASS` stands for a pass.
+    ///
+    pub view: BoardView,
+    ///
+    /// The time used for the move, measured by the client.
+    ///
+    pub time: Duration,
+    ///
+    /// The score of the engine for the side to move in discs, if it searched the move.
+    ///
+    pub score: Option<f64>,
+}
+
+impl GameMove {
+    ///
+    /// Converts a score of the search into discs.
+    ///
+    pub fn score_in_discs(score: i32) -> f64 {
+        score.clamp(-64 * VALUE_PER_DISC, 64 * VALUE_PER_DISC) as f64 / VALUE_PER_DISC as f64
+    }
+}
+
+///
+/// A game from the viewpoint of one of the players, which the book learns from.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayedGame {
+    pub opponent: String,
+    pub color: Color,
+    pub result: GameResult,
+    pub score: u8,
+    pub opponent_score: u8,
+    ///
+    /// Moves without passes like `F5D6C3`.
+    ///
+    pub history: String,
+}
+
+impl PlayedGame {
+    ///
+    /// Returns the disc differential for us.
+    ///
+    pub fn diff(&self) -> i32 {
+        self.score as i32 - self.opponent_score as i32
+    }
+
+    pub fn moves(&self) -> Option<Vec<BoardView>> {
+        parse_history(&self.history)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SavedGame {
+    ///
+    /// The seconds since the Unix epoch when the game started.
+    ///
+    pub date: u64,
+    pub black: String,
+    pub white: String,
+    ///
+    /// The initial clock of each player in milliseconds.
+    ///
+    pub time: u64,
+    pub moves: Vec<GameMove>,
+    ///
+    /// The result for black in discs as written in `RE[]`.
+    ///
+    pub result: i32,
+    ///
+    /// The reason of the end given by the server, like `DOUBLE_PASS`.
+    ///
+    pub reason: String,
+}
+
+impl SavedGame {
+    ///
+    /// Converts the result of a player into the one for black in `RE[]`.
+    ///
+    pub fn black_result(color: Color, result: GameResult, score: u8, opponent_score: u8) -> i32 {
+        let diff = score as i32 - opponent_score as i32;
+        let diff = match result {
+            GameResult::Win if diff <= 0 => 64,
+            GameResult::Lose if diff >= 0 => -64,
+            GameResult::Tie => 0,
+            _ => diff,
+        };
+        match color {
+            Color::Black => diff,
+            Color::White => -diff,
+        }
+    }
+
+    ///
+    /// The moves without passes like `F5D6C3`.
+    ///
+    pub fn history(&self) -> String {
+        self.moves
+            .iter()
+            .filter(|mv| mv.view != PASS)
+            .map(|mv| to_notation(mv.view))
+            .collect()
+    }
+
+    ///
+    /// The numbers of black and white discs on the final board.
+    ///
+    pub fn counts(&self) -> (u8, u8) {
+        let board = new_board(&Color::Black);
+        let (mut black, mut white) = (board.player, board.opponent);
+        for mv in self.moves.iter().filter(|mv| mv.view != PASS) {
+            match mv.color {
+                Color::Black => put(mv.view, &mut black, &mut white),
+                Color::White => put(mv.view, &mut white, &mut black),
+            }
+        }
+        (popcnt64!(black) as u8, popcnt64!(white) as u8)
+    }
+
+    ///
+    /// Converts the game into the record of the player named `name` for the book learning.
+    ///
+    pub fn played_game(&self, name: &str) -> Option<PlayedGame> {
+        let (color, opponent) = if self.black == name {
+            (Color::Black, &self.white)
+        } else if self.white == name {
+            (Color::White, &self.black)
+        } else {
+            return None;
+        };
+        let (black, white) = self.counts();
+        let (result, score, opponent_score) = match color {
+            Color::Black => (self.result, black, white),
+            Color::White => (-self.result, white, black),
+        };
+        Some(PlayedGame {
+            opponent: opponent.clone(),
+            color,
+            result: match result.signum() {
+                1 => GameResult::Win,
+                0 => GameResult::Tie,
+                _ => GameResult::Lose,
+            },
+            score,
+            opponent_score,
+            history: self.history(),
+        })
+    }
+
+    pub fn to_ggf(&self) -> String {
+        let suffix = match self.reason.as_str() {
+            "DOUBLE_PASS" => "",
+            "TIMEOUT" => ":t",
+            _ => ":r",
+        };
+        let mut text = format!(
+            "(;GM[Othello]PC[rinee]DT[{}]PB[{}]PW[{}]RE[{:+.3}{}]TI[{}]TY[8]C[{}]BO[{}]",
+            format_date(self.date),
+            escape(&self.black),
+            escape(&self.white),
+            self.result as f64,
+            suffix,
+            format_clock(self.time),
+            escape(&self.reason),
+            INITIAL_BOARD
+        );
+        for mv in &self.moves {
+            let tag = match mv.color {
+                Color::Black => "B",
+                Color::White => "W",
+            };
+            let notation = if mv.view == PASS {
+                String::from("PA")
+            } else {
+                to_notation(mv.view)
+            };
+            let score = mv.score.map(|score| format!("{:.2}", score));
+            text += &format!(
+                "{}[{}/{}/{:.3}]",
+                tag,
+                notation,
+                score.unwrap_or_default(),
+                mv.time.as_secs_f64()
+            );
+        }
+        text + ";)"
+    }
+
+    pub fn parse_ggf(text: &str) -> Result<Self, Error> {
+        let error = || Error::ParserWithMessage(text.trim().to_string());
+        let mut game = SavedGame {
+            date: 0,
+            black: String::new(),
+            white: String::new(),
+            time: 0,
+            moves: Vec::new(),
+            result: 0,
+            reason: String::new(),
+        };
+
+        for (tag, value) in parse_tags(text).ok_or_else(error)? {
+            match tag.as_str() {
+                "GM" if value != "Othello" => return Err(error()),
+                "BO" if value != INITIAL_BOARD => return Err(error()),
+                // The date only names the file, so a broken one is left unknown.
+                "DT" => game.date = parse_date(&value).unwrap_or(0),
+                "PB" => game.black = value,
+                "PW" => game.white = value,
+                "TI" => game.time = parse_clock(&value).ok_or_else(error)?,
+                "C" => game.reason = value,
+                "RE" => {
+                    let result = value.split(':').next().unwrap_or_default();
+                    game.result = result.parse::<f64>().map_err(|_| error())? as i32;
+                }
+                "B" | "W" => {
+                    let color = if tag == "B" {
+                        Color::Black
+                    } else {
+                        Color::White
+                    };
+                    game.moves
+                        .push(parse_move(color, &value).ok_or_else(error)?);
+                }
+                _ => {}
+            }
+        }
+        Ok(game)
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace(']', "\\]")
+}
+
+///
+/// Formats milliseconds like `15:00`, appending the milliseconds if any.
+///
+fn format_clock(time: u64) -> String {
+    let (secs, millis) = (time / 1000, time % 1000);
+    if millis == 0 {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    } else {
+        format!("{}:{:02}.{:03}", secs / 60, secs % 60, millis)
+    }
+}
+
+///
+/// Parses the main time of a clock like `15:00//02:00` into milliseconds.
+///
+fn parse_clock(clock: &str) -> Option<u64> {
+    let main = clock.split('/').next()?;
+    let mut seconds = 0.0;
+    for part in main.split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    Some((seconds * 1000.0).round() as u64)
+}
+
+///
+/// Formats the seconds since the Unix epoch like `2023.11.14_22:13:20.GMT`.
+///
+fn format_date(date: u64) -> String {
+    let (days, secs) = ((date / 86400) as i64, date % 86400);
+    // The civil calendar from the days since the epoch, in the eras of 400 years from March.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}.{:02}.{:02}_{:02}:{:02}:{:02}.GMT",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+///
+/// Parses a date like `2023.11.14_22:13:20.GMT` into the seconds since the Unix epoch.
+/// The seconds themselves, written by the older versions, are also accepted.
+///
+fn parse_date(value: &str) -> Option<u64> {
+    if let Ok(date) = value.parse() {
+        return Some(date);
+    }
+    let (date, time) = value.strip_suffix(".GMT")?.split_once('_')?;
+    let fields = |text: &str, separator| {
+        text.split(separator)
+            .map(|field| field.parse::<i64>().ok())
+            .collect::<Option<Vec<_>>>()
+    };
+    let (date, time) = (fields(date, '.')?, fields(time, ':')?);
+    let (&[year, month, day], &[hour, minute, second]) = (&date[..], &time[..]) else {
+        return None;
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // The inverse of `format_date`.
+    let year = year - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    u64::try_from(days * 86400 + hour * 3600 + minute * 60 + second).ok()
+}
+
+///
+/// Parses a move like `F5/1.50/2.345`, where the score and the time may be empty.
+///
+fn parse_move(color: Color, value: &str) -> Option<GameMove> {
+    let mut fields = value.split('/');
+    let notation = fields.next()?;
+    let view = if notation.eq_ignore_ascii_case("PA") {
+        PASS
+    } else {
+        from_notation(notation).filter(|&view| view != PASS)?
+    };
+    let score = match fields.next().unwrap_or_default() {
+        "" => None,
+        score => Some(score.parse().ok()?),
+    };
+    let time = match fields.next().unwrap_or_default() {
+        "" => Duration::ZERO,
+        time => Duration::try_from_secs_f64(time.parse().ok()?).ok()?,
+    };
+    Some(GameMove {
+        color,
+        view,
+        time,
+        score,
+    })
+}
+
+///
+/// Splits a game into the pairs of a tag and its value.
+///
+fn parse_tags(text: &str) -> Option<Vec<(String, String)>> {
+    let body = text.trim().strip_prefix("(;")?.strip_suffix(";)")?;
+    let mut tags = Vec::new();
+    let mut chars = body.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut tag = String::new();
+        while let Some(c) = chars.next_if(|c| c.is_ascii_uppercase()) {
+            tag.push(c);
+        }
+        if tag.is_empty() {
+            return chars.next().is_none().then_some(tags);
+        }
+        if chars.next()? != '[' {
+            return None;
+        }
+        let mut value = String::new();
+        loop {
+            match chars.next()? {
+                '\\' => value.push(chars.next()?),
+                ']' => break,
+                c => value.push(c),
+            }
+        }
+        tags.push((tag, value));
+    }
+}
+
+///
+/// Saves the game into a new file of the directory and returns its path.
+///
+pub fn save_game(dir: &str, game: &SavedGame) -> Result<PathBuf, Error> {
+    std::fs::create_dir_all(dir)?;
+    let name: String = format!("{}_{}_{}", game.date, game.black, game.white)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    for i in 0.. {
+        let path = match i {
+            0 => Path::new(dir).join(format!("{}.ggf", name)),
+            _ => Path::new(dir).join(format!("{}_{}.ggf", name, i)),
+        };
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                writeln!(file, "{}", game.to_ggf())?;
+                return Ok(path);
+            }
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    unreachable!()
+}
+
+///
+/// Loads the games of the `.ggf` files in the directory, ordered by the file names.
+/// The files which cannot be read or parsed are reported and skipped.
+///
+pub fn load_games(dir: &str) -> Result<Vec<SavedGame>, Error> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "ggf") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut games = Vec::new();
+    for path in paths {
+        let file_games = std::fs::read_to_string(&path)
+            .map_err(Error::from)
+            .and_then(|text| {
+                text.lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(SavedGame::parse_ggf)
+                    .collect::<Result<Vec<_>, _>>()
+            });
+        match file_games {
+            Ok(file_games) => games.extend(file_games),
+            Err(_) => write_log!(WARN, "Skipped the broken record {}.", path.display()),
+        }
+    }
+    Ok(games)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::board::parse_history;
+
+    #[test]
+    fn test_ggf() {
+        let moves = parse_history("E6F4E3F6G5D6E7F5C5")
+            .unwrap()
+            .into_iter()
+            .enumerate()
+            .map(|(i, view)| GameMove {
+                color: if i % 2 == 0 {
+                    Color::Black
+                } else {
+                    Color::White
+                },
+                view,
+                time: Duration::from_millis(125 * i as u64),
+                score: (i % 2 == 0).then_some(i as f64 - 0.5),
+            })
+            .collect();
+
+        let game = SavedGame {
+            date: 1700000000,
+            black: String::from("rinee"),
+            white: String::from("some]one"),
+            time: 60000,
+            moves,
+            result: SavedGame::black_result(Color::White, GameResult::Lose, 0, 13),
+            reason: String::from("DOUBLE_PASS"),
+        };
+        assert_eq!(game.result, 13);
+
+        let text = game.to_ggf();
+        assert!(text.starts_with(
+            "(;GM[Othello]PC[rinee]DT[2023.11.14_22:13:20.GMT]PB[rinee]PW[some\\]one]RE[+13.000]TI[1:00]"
+        ));
+        assert!(text.contains("B[E6/-0.50/0.000]W[F4//0.125]B[E3/1.50/0.250]"));
+        assert_eq!(SavedGame::parse_ggf(&text).unwrap(), game);
+
+        let played = game.played_game("some]one").unwrap();
+        assert_eq!(played.result, GameResult::Lose);
+        assert_eq!((played.score, played.opponent_score), (0, 13));
+        assert_eq!(played.history, "E6F4E3F6G5D6E7F5C5");
+        assert_eq!(played.diff(), -13);
+        assert!(game.played_game("nobody").is_none());
+
+        // The winner by a violation gets all the discs.
+        assert_eq!(
+            SavedGame::black_result(Color::Black, GameResult::Lose, 40, 24),
+            -64
+        );
+        assert_eq!(
+            parse_move(Color::White, "PA//").map(|mv| mv.view),
+            Some(PASS)
+        );
+        assert!(SavedGame::parse_ggf("(;GM[Othello]B[Z9];)").is_err());
+    }
+
+    #[test]
+    fn test_date() {
+        for date in [0, 951782400, 1700000000, 4107542399] {
+            assert_eq!(parse_date(&format_date(date)), Some(date));
+        }
+        assert_eq!(format_date(951782400), "2000.02.29_00:00:00.GMT");
+        assert_eq!(parse_date("1700000000"), Some(1700000000));
+        assert_eq!(parse_date("yesterday"), None);
+
+        // A broken date does not lose the game.
+        let game = SavedGame::parse_ggf("(;GM[Othello]DT[2023.13.01_00:00:00.GMT];)").unwrap();
+        assert_eq!(game.date, 0);
+    }
+
+    #[test]
+    fn test_load_games() {
+        let dir = std::env::temp_dir().join(format!("rinee_ggf_{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        let _ = std::fs::remove_dir_all(dir);
+        let game = SavedGame::parse_ggf("(;GM[Othello]PB[a]PW[b]B[F5];)").unwrap();
+        save_game(dir, &game).unwrap();
+        std::fs::write(Path::new(dir).join("broken.ggf"), "(;GM[Go];)").unwrap();
+
+        assert_eq!(load_games(dir).unwrap(), vec![game]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
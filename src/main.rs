@@ -1,7 +1,8 @@
 use std::io::Write;
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 
+use crate::book::OpeningBook;
 use crate::connection::play_game;
 use crate::proto::Error;
 
@@ -11,6 +12,9 @@ use crate::proto::Error;
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     ///
     /// A hostname to connect to.
     ///
@@ -28,6 +32,576 @@ struct Args {
     ///
     #[arg(short, long, default_value = "anonymous")]
     pub name: String,
+
+    ///
+    /// The number of the sessions to play, each of which ends with `BYE`. 0 plays forever.
+    ///
+    #[arg(long, default_value = "1")]
+    pub sessions: u32,
+
+    ///
+    /// How many times in a row to reconnect after losing the connection. 0 exits on the first error.
+    ///
+    #[arg(long, default_value = "10")]
+    pub retries: u32,
+
+    ///
+    /// An opening book in the text or binary format. It is also searched next to the executable
+    /// if the path is relative. The book embedded into the executable is used if omitted.
+    ///
+    #[arg(short, long)]
+    pub book: Option<String>,
+
+    ///
+    /// Plays without the opening book.
+    ///
+    #[arg(long)]
+    pub no_book: bool,
+
+    ///
+    /// Book moves within this many discs from the best one are chosen at random, weighted by the number of games.
+    ///
+    #[arg(long, default_value = "0")]
+    pub book_margin: f64,
+
+    ///
+    /// The first move as black, like `C4`, or `book` to choose it from the book.
+    ///
+    #[arg(long, default_value = "C4")]
+    pub first_move: String,
+
+    ///
+    /// An engine to choose moves.
+    ///
+    #[arg(short, long, value_enum, default_value_t = Engine::AlphaBeta)]
+    pub engine: Engine,
+
+    ///
+    /// Lets the alpha-beta engine think on the opponent's time.
+    ///
+    #[arg(long)]
+    pub ponder: bool,
+
+    ///
+    /// Milliseconds reserved on every move for the network and the server.
+    ///
+    #[arg(long, default_value = "50")]
+    pub latency_margin: u64,
+
+    ///
+    /// The depth searched by the fixed-depth engine.
+    ///
+    #[arg(long, default_value = "4")]
+    pub depth: u8,
+
+    ///
+    /// A seed of random choices of the random and MCTS engines and the book.
+    ///
+    #[arg(long, default_value = "0")]
+    pub seed: u64,
+
+    ///
+    /// The exploration constant of MCTS.
+    ///
+    #[arg(long, default_value_t = mcts::DEFAULT_EXPLORATION)]
+    pub mcts_exploration: f64,
+
+    ///
+    /// Lets MCTS rollouts prefer moves with better evaluation.
+    ///
+    #[arg(long)]
+    pub mcts_heuristic: bool,
+
+    ///
+    /// A file of Multi-ProbCut parameters. The built-in ones are used if omitted.
+    ///
+    #[arg(long)]
+    pub mpc: Option<String>,
+
+    ///
+    /// How many standard deviations Multi-ProbCut requires to cut a node.
+    ///
+    #[arg(long, default_value_t = probcut::DEFAULT_THRESHOLD)]
+    pub mpc_threshold: f64,
+
+    ///
+    /// Disables Multi-ProbCut.
+    ///
+    #[arg(long)]
+    pub no_mpc: bool,
+
+    ///
+    /// A directory to save the records of the played games in the GGF format to,
+    /// which the book learns from.
+    ///
+    #[arg(long, default_value = ggf::DEFAULT_RECORD_DIR)]
+    pub record_dir: String,
+
+    ///
+    /// Does not record the played games.
+    ///
+    #[arg(long)]
+    pub no_record: bool,
+
+    ///
+    /// A CSV file to append the search statistics of every move to.
+    ///
+    #[arg(long)]
+    pub stats: Option<String>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Engine {
+    ///
+    /// Iterative deepening alpha-beta search.
+    ///
+    AlphaBeta,
+    ///
+    /// Monte Carlo Tree Search.
+    ///
+    Mcts,
+    ///
+    /// Random moves.
+    ///
+    Random,
+    ///
+    /// The move flipping the most discs.
+    ///
+    Greedy,
+    ///
+    /// Alpha-beta search to the depth given by `--depth`.
+    ///
+    FixedDepth,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    ///
+    /// Tools for the opening book.
+    ///
+    Book {
+        #[command(subcommand)]
+        command: BookCommand,
+    },
+
+    ///
+    /// Tools for WTHOR game databases.
+    ///
+    Wtb {
+        #[command(subcommand)]
+        command: WtbCommand,
+    },
+
+    ///
+    /// Serves games between two clients on this machine.
+    ///
+    Server {
+        ///
+        /// A port to listen on.
+        ///
+        #[arg(short, long, default_value = "3000")]
+        port: u16,
+
+        ///
+        /// The number of games played in the session.
+        ///
+        #[arg(long, default_value = "2")]
+        games: u32,
+
+        ///
+        /// The time of each player for a game in milliseconds.
+        ///
+        #[arg(long, default_value = "60000")]
+        time: u64,
+    },
+
+    ///
+    /// Lists the scores of every legal move in a position.
+    ///
+    Analyze {
+        ///
+        /// A transcript like `F5D6C3`, or 64 squares from A1 to H8 of `X`, `O` and `-`
+        /// followed by the side to move like `X`.
+        ///
+        #[arg(num_args = 1..=2, required = true)]
+        input: Vec<String>,
+
+        ///
+        /// The time of the search in milliseconds.
+        ///
+        #[arg(long, default_value = "2000")]
+        time: u64,
+
+        ///
+        /// Searches to the depth instead of for the time.
+        ///
+        #[arg(long)]
+        depth: Option<u8>,
+    },
+
+    ///
+    /// Plays a game against the engine in the terminal.
+    ///
+    Play {
+        ///
+        /// Plays white instead of black.
+        ///
+        #[arg(long)]
+        white: bool,
+
+        ///
+        /// The time for the engine to think on a move and for a hint in milliseconds.
+        ///
+        #[arg(long, default_value = "1000")]
+        time: u64,
+    },
+
+    ///
+    /// Plays a match between two engines in the process.
+    ///
+    Arena {
+        ///
+        /// The options of the first engine as on the command line, like `-e alpha-beta --no-mpc`.
+        ///
+        #[arg(long, allow_hyphen_values = true, default_value = "")]
+        first: String,
+
+        ///
+        /// The options of the second engine.
+        ///
+        #[arg(long, allow_hyphen_values = true, default_value = "")]
+        second: String,
+
+        ///
+        /// The number of openings, each of which is played from both colors.
+        ///
+        #[arg(long, default_value = "50")]
+        openings: usize,
+
+        ///
+        /// The number of random moves in the openings.
+        ///
+        #[arg(long, default_value = "6")]
+        opening_plies: usize,
+
+        ///
+        /// The time for a move in milliseconds.
+        ///
+        #[arg(long, default_value = "100")]
+        time: u64,
+
+        ///
+        /// The number of games played in parallel, at most the number of the CPUs
+        /// and a quarter of them by default, as every search runs a thread per legal move.
+        ///
+        #[arg(long)]
+        jobs: Option<usize>,
+
+        ///
+        /// A seed of the openings.
+        ///
+        #[arg(long, default_value = "0")]
+        seed: u64,
+
+        ///
+        /// Stops early by the SPRT between the two Elo differences, like `--sprt 0 10`.
+        ///
+        #[arg(long, num_args = 2, allow_hyphen_values = true)]
+        sprt: Option<Vec<f64>>,
+    },
+
+    ///
+    /// Benchmarks of the engine.
+    ///
+    Bench {
+        #[command(subcommand)]
+        command: BenchCommand,
+    },
+
+    ///
+    /// Tools for Multi-ProbCut.
+    ///
+    Mpc {
+        #[command(subcommand)]
+        command: MpcCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum BookCommand {
+    ///
+    /// Builds the book from WTHOR databases.
+    ///
+    Build {
+        ///
+        /// WTHOR `.wtb` files to read.
+        ///
+        #[arg(long, num_args = 1.., required = true)]
+        wtb: Vec<String>,
+
+        ///
+        /// The number of moves read from the beginning of each game.
+        ///
+        #[arg(long, default_value = "20")]
+        depth: usize,
+
+        ///
+        /// Positions appearing in fewer games than this are dropped.
+        ///
+        #[arg(long, default_value = "50")]
+        threshold: u32,
+
+        ///
+        /// Pools games only over identical positions instead of all the symmetric ones.
+        ///
+        #[arg(long)]
+        no_symmetry: bool,
+
+        ///
+        /// A file to write the book to.
+        ///
+        #[arg(short, long, default_value = book::DEFAULT_BOOK_FILE)]
+        output: String,
+    },
+
+    ///
+    /// Searches the leaves of the book and backs the values up through it.
+    ///
+    Deepen {
+        ///
+        /// A book to deepen.
+        ///
+        #[arg(short, long, default_value = book::DEFAULT_BOOK_FILE)]
+        input: String,
+
+        ///
+        /// The depth searched on each leaf, at least 1.
+        ///
+        #[arg(long, default_value = "10", value_parser = clap::value_parser!(u8).range(1..))]
+        depth: u8,
+
+        ///
+        /// Moves favored by the games are reported when the search finds a better one by this margin.
+        ///
+        #[arg(long, default_value = "128")]
+        margin: i32,
+
+        ///
+        /// A file to write the book to.
+        ///
+        #[arg(short, long, default_value = book::DEFAULT_BOOK_FILE)]
+        output: String,
+    },
+
+    ///
+    /// Converts the book into the binary format.
+    ///
+    Pack {
+        ///
+        /// A book in the text format.
+        ///
+        #[arg(short, long, default_value = book::DEFAULT_BOOK_FILE)]
+        input: String,
+
+        ///
+        /// A file to write the binary book to.
+        ///
+        #[arg(short, long, default_value = "book.bin")]
+        output: String,
+    },
+
+    ///
+    /// Reports the lines of the book which cannot be parsed
+    /// and the moves which cannot be played on their positions. Fails if any is found.
+    ///
+    Verify {
+        ///
+        /// A book to verify, in the text format or in the binary one.
+        ///
+        #[arg(short, long, default_value = book::DEFAULT_BOOK_FILE)]
+        input: String,
+    },
+
+    ///
+    /// Learns from the games played by the client.
+    /// The client plays the embedded book unless `--book` is given,
+    /// so run it with `--book <OUTPUT>` to play the learned one.
+    ///
+    Learn {
+        ///
+        /// A directory of GGF records where the games of the player named by `--name`
+        /// are learned.
+        ///
+        #[arg(long, default_value = ggf::DEFAULT_RECORD_DIR)]
+        games: String,
+
+        ///
+        /// A book to learn into.
+        ///
+        #[arg(short, long, default_value = book::DEFAULT_BOOK_FILE)]
+        input: String,
+
+        ///
+        /// The number of moves read from the beginning of each game.
+        ///
+        #[arg(long, default_value = "20")]
+        depth: usize,
+
+        ///
+        /// Moves in the book are penalized only after they lose in most of this many games.
+        ///
+        #[arg(long, default_value = "3")]
+        min_games: u32,
+
+        ///
+        /// Learns only the games against the opponent of the name.
+        ///
+        #[arg(long)]
+        opponent: Option<String>,
+
+        ///
+        /// A file to write the book to.
+        ///
+        #[arg(short, long, default_value = book::DEFAULT_BOOK_FILE)]
+        output: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum WtbCommand {
+    ///
+    /// Prints the games with the names of the players and the tournaments.
+    ///
+    Show {
+        ///
+        /// A `.wtb` file to print.
+        ///
+        file: String,
+
+        ///
+        /// A `.jou` file of the player names.
+        ///
+        #[arg(long)]
+        jou: Option<String>,
+
+        ///
+        /// A `.trn` file of the tournament names.
+        ///
+        #[arg(long)]
+        trn: Option<String>,
+
+        ///
+        /// The number of games to print.
+        ///
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+
+    ///
+    /// Replays every game and reports the invalid ones.
+    ///
+    Check {
+        ///
+        /// `.wtb` files to check.
+        ///
+        #[arg(num_args = 1.., required = true)]
+        files: Vec<String>,
+
+        ///
+        /// A `.wtb` file to write the valid games to.
+        ///
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum BenchCommand {
+    ///
+    /// Solves the FFO endgame test positions exactly.
+    ///
+    Ffo {
+        ///
+        /// The first position to solve, the first one of the positions by default.
+        ///
+        #[arg(long)]
+        first: Option<u32>,
+
+        ///
+        /// The last position to solve, the last one of the positions by default.
+        ///
+        #[arg(long)]
+        last: Option<u32>,
+
+        ///
+        /// A file of positions to solve instead of the bundled ones,
+        /// in lines like `40 <64 squares> X A2 +38`.
+        ///
+        #[arg(long)]
+        file: Option<String>,
+    },
+
+    ///
+    /// Measures the board functions, the evaluation and the fixed-depth search,
+    /// comparing them with the baseline.
+    ///
+    Perf {
+        ///
+        /// The number of times each function runs over the positions.
+        ///
+        #[arg(long, default_value = "20000")]
+        rounds: usize,
+
+        ///
+        /// The depth of the search.
+        ///
+        #[arg(long, default_value = "6")]
+        depth: u8,
+
+        ///
+        /// A file of the baseline results.
+        ///
+        #[arg(long, default_value = bench::DEFAULT_BASELINE_FILE)]
+        baseline: String,
+
+        ///
+        /// Overwrites the baseline with the results.
+        ///
+        #[arg(long)]
+        save: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum MpcCommand {
+    ///
+    /// Fits the parameters from self-play positions.
+    ///
+    Fit {
+        ///
+        /// The number of self-play games.
+        ///
+        #[arg(long, default_value = "64")]
+        games: usize,
+
+        ///
+        /// The deepest depth to fit.
+        ///
+        #[arg(long, default_value = "8")]
+        max_depth: u8,
+
+        ///
+        /// A seed of the random moves.
+        ///
+        #[arg(long, default_value = "0")]
+        seed: u64,
+
+        ///
+        /// A file to write the parameters to.
+        ///
+        #[arg(short, long, default_value = "mpc.txt")]
+        output: String,
+    },
 }
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 20)]
@@ -64,8 +638,145 @@ ADD `--release` BUILD FLAG TO LET THIS AI RUN WITHOUT RESTRICTIONS."
 
     write_log!(LOG, "Rinee is started.");
 
-    match play_game(&args).await {
-        Ok(_) => println!("The game ends. Enjoy your day!"),
+    let result = match &args.command {
+        None => play_game(&args)
+            .await
+            .map(|_| println!("The game ends. Enjoy your day!")),
+        Some(Command::Book {
+            command:
+                BookCommand::Build {
+                    wtb,
+                    depth,
+                    threshold,
+                    no_symmetry,
+                    output,
+                },
+        }) => {
+            let options = book::BuildOptions {
+                depth: *depth,
+                threshold: *threshold,
+                symmetry: !no_symmetry,
+            };
+            build_book(wtb, &options, output)
+        }
+        Some(Command::Book {
+            command:
+                BookCommand::Deepen {
+                    input,
+                    depth,
+                    margin,
+                    output,
+                },
+        }) => deepen_book(input, *depth, *margin, output, &args),
+        Some(Command::Book {
+            command: BookCommand::Pack { input, output },
+        }) => pack_book(input, output),
+        Some(Command::Book {
+            command: BookCommand::Verify { input },
+        }) => book::verify(input),
+        Some(Command::Book {
+            command:
+                BookCommand::Learn {
+                    games,
+                    input,
+                    depth,
+                    min_games,
+                    opponent,
+                    output,
+                },
+        }) => {
+            let options = book::LearnOptions {
+                depth: *depth,
+                min_games: *min_games,
+                opponent: opponent.clone(),
+            };
+            learn_book(games, input, &options, output, &args.name)
+        }
+        Some(Command::Wtb {
+            command:
+                WtbCommand::Show {
+                    file,
+                    jou,
+                    trn,
+                    limit,
+                },
+        }) => show_wtb(file, jou.as_deref(), trn.as_deref(), *limit),
+        Some(Command::Wtb {
+            command: WtbCommand::Check { files, output },
+        }) => check_wtb(files, output.as_deref()),
+        Some(Command::Server { port, games, time }) => server::run_server(&server::ServerOptions {
+            port: *port,
+            games: *games,
+            time: *time,
+        }),
+        Some(Command::Analyze { input, time, depth }) => {
+            let limit = match depth {
+                Some(depth) => analyze::Limit::Depth(*depth),
+                None => analyze::Limit::Time(std::time::Duration::from_millis(*time)),
+            };
+            let options = probcut::load_probcut(&args).map(|probcut| agent::SearchOptions {
+                probcut,
+                tt: Some(std::sync::Arc::new(tt::TranspositionTable::new(
+                    tt::DEFAULT_BITS,
+                ))),
+                abort: None,
+            });
+            options.and_then(|options| analyze::run(&input.join(" "), &limit, &options))
+        }
+        Some(Command::Play { white, time }) => {
+            play_terminal(*white, std::time::Duration::from_millis(*time), &args)
+        }
+        Some(Command::Arena {
+            first,
+            second,
+            openings,
+            opening_plies,
+            time,
+            jobs,
+            seed,
+            sprt,
+        }) => {
+            let options = arena::ArenaOptions {
+                openings: *openings,
+                opening_plies: *opening_plies,
+                time: std::time::Duration::from_millis(*time),
+                jobs: arena::jobs(*jobs),
+                seed: *seed,
+                sprt: sprt.as_ref().map(|sprt| (sprt[0], sprt[1])),
+            };
+            run_arena(first, second, &options)
+        }
+        Some(Command::Bench {
+            command: BenchCommand::Ffo { first, last, file },
+        }) => bench::run_ffo(file.as_deref(), *first, *last),
+        Some(Command::Bench {
+            command:
+                BenchCommand::Perf {
+                    rounds,
+                    depth,
+                    baseline,
+                    save,
+                },
+        }) => {
+            let options = bench::PerfOptions {
+                rounds: *rounds,
+                depth: *depth,
+            };
+            bench::run_perf(&options, baseline, *save)
+        }
+        Some(Command::Mpc {
+            command:
+                MpcCommand::Fit {
+                    games,
+                    max_depth,
+                    seed,
+                    output,
+                },
+        }) => probcut::run_fit(*games, *max_depth, *seed, output),
+    };
+
+    match result {
+        Ok(_) => return,
         Err(Error::IO(e)) => {
             write_log!(ERROR, "Detected an I/O error: {}", e);
         }
@@ -75,13 +786,248 @@ ADD `--release` BUILD FLAG TO LET THIS AI RUN WITHOUT RESTRICTIONS."
         Err(Error::ParserWithMessage(message)) => {
             write_log!(ERROR, "Detected an error on parsing \"{}\".", message);
         }
+        Err(Error::Failed(message)) => {
+            write_log!(ERROR, "{}", message);
+        }
+    }
+    std::process::exit(1);
+}
+
+fn build_book(files: &[String], options: &book::BuildOptions, output: &str) -> Result<(), Error> {
+    let mut records = Vec::new();
+    for file in files {
+        let (_, file_records) = wtb::read_wtb(file)?;
+        let count = file_records.len();
+        let (valid, invalid): (Vec<_>, Vec<_>) = file_records
+            .into_iter()
+            .partition(|record| record.validate().is_ok());
+        write_log!(
+            LOG,
+            "Read {} games from {}, skipping {} invalid ones.",
+            count,
+            file,
+            invalid.len()
+        );
+        records.extend(valid);
+    }
+
+    let book = book::build(&records, options);
+    std::fs::write(output, book.to_text())?;
+    println!(
+        "The book of {} positions is written to {}.",
+        book.len(),
+        output
+    );
+    Ok(())
+}
+
+fn show_wtb(
+    file: &str,
+    jou: Option<&str>,
+    trn: Option<&str>,
+    limit: Option<usize>,
+) -> Result<(), Error> {
+    let (header, records) = wtb::read_wtb(file)?;
+    let read_names = |path: Option<&str>, record_size| match path {
+        Some(path) => wtb::read_names(path, record_size).map(|(_, names)| names),
+        None => Ok(Vec::new()),
+    };
+    let players = read_names(jou, wtb::JOU_RECORD_SIZE)?;
+    let tournaments = read_names(trn, wtb::TRN_RECORD_SIZE)?;
+    let name = |names: &[String], index: u16| {
+        names
+            .get(index as usize)
+            .cloned()
+            .unwrap_or_else(|| format!("#{}", index))
+    };
+
+    println!(
+        "{} games of {} (created on {:02}{:02}-{:02}-{:02}, perfect play from {} empties)",
+        header.record_num,
+        header.game_year,
+        header.century,
+        header.year,
+        header.month,
+        header.day,
+        header.depth
+    );
+    for record in records.iter().take(limit.unwrap_or(records.len())) {
+        println!(
+            "{} | {} - {} | {} ({}) | {}",
+            name(&tournaments, record.tournament),
+            name(&players, record.black),
+            name(&players, record.white),
+            record.black_num,
+            record.black_best,
+            record.transcript()
+        );
+    }
+    Ok(())
+}
+
+fn check_wtb(files: &[String], output: Option<&str>) -> Result<(), Error> {
+    let mut header = None;
+    let mut valid = Vec::new();
+    let mut invalid = 0;
+    for file in files {
+        let (file_header, records) = wtb::read_wtb(file)?;
+        header.get_or_insert(file_header);
+        for (i, record) in records.into_iter().enumerate() {
+            match record.validate() {
+                Ok(()) => valid.push(record),
+                Err(reason) => {
+                    println!("{}: game {}: {}", file, i + 1, reason);
+                    invalid += 1;
+                }
+            }
+        }
+    }
+    println!(
+        "{} games are valid and {} are invalid.",
+        valid.len(),
+        invalid
+    );
+
+    if let (Some(output), Some(header)) = (output, header) {
+        wtb::write_wtb(output, &header, &valid)?;
+        println!("The valid games are written to {}.", output);
+    }
+    Ok(())
+}
+
+fn deepen_book(
+    input: &str,
+    depth: u8,
+    margin: i32,
+    output: &str,
+    args: &Args,
+) -> Result<(), Error> {
+    let mut book = book::Book::load(input)?;
+    let options = agent::SearchOptions {
+        probcut: probcut::load_probcut(args)?,
+        tt: Some(std::sync::Arc::new(tt::TranspositionTable::new(
+            tt::DEFAULT_BITS,
+        ))),
+        abort: None,
+    };
+    book.deepen(depth, &options);
+
+    for (board, favored, best) in book.refuted(margin) {
+        println!(
+            "{:016X} {:016X}: {} (score = {:.2}, value = {}) is refuted by {} (value = {})",
+            board.player,
+            board.opponent,
+            board::to_notation(favored.view),
+            favored.score,
+            favored.value.unwrap_or_default(),
+            board::to_notation(best.view),
+            best.value.unwrap_or_default()
+        );
     }
+
+    std::fs::write(output, book.to_text())?;
+    println!("The deepened book is written to {}.", output);
+    Ok(())
+}
+
+fn play_terminal(white: bool, time: std::time::Duration, args: &Args) -> Result<(), Error> {
+    let mut agent = agent::create_agent(args)?;
+    let options = play::PlayOptions {
+        color: if white {
+            proto::Color::White
+        } else {
+            proto::Color::Black
+        },
+        time,
+        hint: agent::SearchOptions {
+            probcut: probcut::load_probcut(args)?,
+            tt: None,
+            abort: None,
+        },
+    };
+    play::play(
+        &mut std::io::stdin().lock(),
+        &mut std::io::stdout(),
+        agent.as_mut(),
+        &options,
+    )
+}
+
+///
+/// Parses the options of an engine in the arena as the ones of the client.
+///
+fn engine_args(options: &str) -> Result<Args, Error> {
+    Args::try_parse_from(std::iter::once("rinee").chain(options.split_whitespace()))
+        .map_err(|_| Error::ParserWithMessage(options.to_string()))
+}
+
+fn run_arena(first: &str, second: &str, options: &arena::ArenaOptions) -> Result<(), Error> {
+    let report = arena::run(&engine_args(first)?, &engine_args(second)?, options)?;
+    println!("First: {}", first);
+    println!("Second: {}", second);
+    println!("{}", report);
+    Ok(())
+}
+
+fn pack_book(input: &str, output: &str) -> Result<(), Error> {
+    let book = book::Book::load(input)?;
+    let bytes = packed_book::pack(&book);
+    std::fs::write(output, &bytes)?;
+    println!(
+        "The book of {} positions is packed into {} bytes in {}.",
+        book.len(),
+        bytes.len(),
+        output
+    );
+    Ok(())
+}
+
+fn learn_book(
+    games: &str,
+    input: &str,
+    options: &book::LearnOptions,
+    output: &str,
+    name: &str,
+) -> Result<(), Error> {
+    let games: Vec<_> = ggf::load_games(games)?
+        .iter()
+        .filter_map(|game| game.played_game(name))
+        .collect();
+    let mut book = book::Book::load(input)?;
+    let summary = book.learn(&games, options);
+    std::fs::write(output, book.to_text())?;
+    println!(
+        "Learned from {} games: {} moves added, {} merged and {} penalized. The book is written to {}.",
+        games.len(),
+        summary.added,
+        summary.merged,
+        summary.penalized,
+        output
+    );
+    Ok(())
 }
 
 mod agent;
+mod analyze;
+mod arena;
+mod baseline;
+mod bench;
 mod board;
+mod book;
+mod clock;
 mod connection;
+mod endgame;
+mod ggf;
 mod log;
+mod mcts;
+mod packed_book;
 mod parser;
+mod play;
+mod ponder;
+mod probcut;
 mod proto;
+mod server;
+mod stats;
+mod tt;
 mod util;
+mod wtb;
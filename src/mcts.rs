@@ -0,0 +1,513 @@
+//
+// Monte Carlo Tree Search with UCT.
+//
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use crate::{
+    agent::{evaluate, Agent, SearchResult},
+    board::{format_line, get_valid_moves, put, to_notation, Board, BoardView, PASS},
+    clock::TimeLimit,
+    popcnt64,
+    stats::SearchStats,
+    tzcnt64,
+    util::Rng,
+    write_log,
+};
+
+#[derive(Clone)]
+pub struct MctsOptions {
+    ///
+    /// The constant `c` of UCT.
+    ///
+    pub exploration: f64,
+    ///
+    /// Whether rollouts prefer moves with better `evaluate`.
+    ///
+    pub heuristic: bool,
+}
+
+pub struct MctsAgent {
+    options: MctsOptions,
+    rng: Rng,
+    abort: Arc<AtomicBool>,
+}
+
+impl MctsAgent {
+    pub fn new(options: MctsOptions, seed: u64) -> Self {
+        MctsAgent {
+            options,
+            rng: Rng::new(seed),
+            abort: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl Agent for MctsAgent {
+    fn choose_move(&mut self, board: Board, limit: TimeLimit) -> SearchResult {
+        select_best_move(board, limit, &self.options, &mut self.rng, &self.abort)
+    }
+
+    fn abort_handle(&self) -> Option<Arc<AtomicBool>> {
+        Some(self.abort.clone())
+    }
+}
+
+pub const DEFAULT_EXPLORATION: f64 = std::f64::consts::SQRT_2;
+
+///
+/// The rate of random moves in heuristic rollouts.
+///
+const ROLLOUT_EPSILON: f64 = 0.25;
+
+struct Node {
+    ///
+    /// The board from the viewpoint of the side to move.
+    ///
+    board: Board,
+    view: BoardView,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: BoardView,
+    ///
+    /// Whether the side to move has to pass.
+    ///
+    pass: bool,
+    visits: u32,
+    ///
+    /// The sum of the results for the side which moved into this node.
+    ///
+    wins: f64,
+}
+
+impl Node {
+    fn new(board: Board, view: BoardView, parent: Option<usize>) -> Self {
+        let valid = get_valid_moves(board.player, board.opponent);
+        let pass = valid == 0 && get_valid_moves(board.opponent, board.player) != 0;
+        Node {
+            board,
+            view,
+            parent,
+            children: Vec::new(),
+            untried: valid,
+            pass,
+            visits: 0,
+            wins: 0.0,
+        }
+    }
+
+    fn is_expandable(&self) -> bool {
+        self.untried != 0 || (self.pass && self.children.is_empty())
+    }
+}
+
+#[inline]
+fn play(board: Board, view: BoardView) -> Board {
+    let mut board = board;
+    if view != PASS {
+        put(view, &mut board.player, &mut board.opponent);
+    }
+    Board {
+        player: board.opponent,
+        opponent: board.player,
+    }
+}
+
+///
+/// Returns the result for the side to move: 1 for a win, 0.5 for a tie and 0 for a loss.
+///
+fn result(board: Board) -> f64 {
+    let pl = popcnt64!(board.player);
+    let op = popcnt64!(board.opponent);
+    if pl > op {
+        1.0
+    } else if pl < op {
+        0.0
+    } else {
+        0.5
+    }
+}
+
+fn rollout(board: Board, heuristic: bool, rng: &mut Rng) -> f64 {
+    let mut board = board;
+    // Whether the side to move is the one at the beginning.
+    let mut same_side = true;
+    loop {
+        let valid = get_valid_moves(board.player, board.opponent);
+        if valid == 0 {
+            if get_valid_moves(board.opponent, board.player) == 0 {
+                let score = result(board);
+                return if same_side { score } else { 1.0 - score };
+            }
+            board = play(board, PASS);
+            same_side = !same_side;
+            continue;
+        }
+
+        let view = if heuristic && rng.next_f64() >= ROLLOUT_EPSILON {
+            let mut best = 0;
+            let mut best_score = i32::MIN;
+            let mut counter = valid;
+            while counter != 0 {
+                let view = 1 << tzcnt64!(counter);
+                counter ^= view;
+                // The next board is from the viewpoint of the opponent.
+                let score = -evaluate(play(board, view));
+                if score > best_score {
+                    best = view;
+                    best_score = score;
+                }
+            }
+            best
+        } else {
+            rng.pick(valid)
+        };
+        board = play(board, view);
+        same_side = !same_side;
+    }
+}
+
+fn select_child(nodes: &[Node], index: usize, exploration: f64) -> usize {
+    let node = &nodes[index];
+    let log_visits = (node.visits as f64).ln();
+    *node
+        .children
+        .iter()
+        .max_by(|&&a, &&b| {
+            let ucb = |child: &Node| {
+                child.wins / child.visits as f64
+                    + exploration * (log_visits / child.visits as f64).sqrt()
+            };
+            ucb(&nodes[a]).total_cmp(&ucb(&nodes[b]))
+        })
+        .unwrap()
+}
+
+///
+/// Searches until the soft limit. The hard limit and the abort flag stop the search even in
+/// the middle of a batch of simulations.
+///
+pub fn select_best_move(
+    board: Board,
+    limit: TimeLimit,
+    options: &MctsOptions,
+    rng: &mut Rng,
+    abort: &AtomicBool,
+) -> SearchResult {
+    let start = Instant::now();
+    let valid = get_valid_moves(board.player, board.opponent);
+    if popcnt64!(valid) <= 1 {
+        return SearchResult {
+            best: if valid == 0 { None } else { Some(valid) },
+            score: None,
+            stats: SearchStats::default(),
+        };
+    }
+
+    let mut nodes = vec![Node::new(board, PASS, None)];
+    let mut stats = SearchStats::default();
+
+    'search: loop {
+        for _ in 0..64 {
+            // Selection.
+            let mut index = 0;
+            let mut depth = 0;
+            while !nodes[index].is_expandable() && !nodes[index].children.is_empty() {
+                index = select_child(&nodes, index, options.exploration);
+                depth += 1;
+            }
+
+            // Expansion.
+            if nodes[index].is_expandable() {
+                let view = if nodes[index].pass {
+                    PASS
+                } else {
+                    let view = rng.pick(nodes[index].untried);
+                    nodes[index].untried ^= view;
+                    view
+                };
+                let child = Node::new(play(nodes[index].board, view), view, Some(index));
+                nodes.push(child);
+                let child = nodes.len() - 1;
+                nodes[index].children.push(child);
+                index = child;
+                depth += 1;
+            }
+            stats.selective_depth = stats.selective_depth.max(depth);
+
+            // Simulation. The result is for the side which moved into the node.
+            let mut score = 1.0 - rollout(nodes[index].board, options.heuristic, rng);
+            stats.nodes += 1;
+
+            // Backpropagation.
+            let mut current = Some(index);
+            while let Some(index) = current {
+                nodes[index].visits += 1;
+                nodes[index].wins += score;
+                score = 1.0 - score;
+                current = nodes[index].parent;
+            }
+
+            if start.elapsed() >= limit.hard || abort.load(Ordering::Relaxed) {
+                break 'search;
+            }
+        }
+
+        if start.elapsed() >= limit.soft {
+            break;
+        }
+    }
+    stats.time = start.elapsed();
+
+    let best = *nodes[0]
+        .children
+        .iter()
+        .max_by_key(|&&child| nodes[child].visits)
+        .unwrap();
+
+    // The most visited line.
+    let mut line = Vec::new();
+    let mut index = best;
+    loop {
+        line.push(nodes[index].view);
+        match nodes[index]
+            .children
+            .iter()
+            .max_by_key(|&&child| nodes[child].visits)
+        {
+            Some(&child) => index = child,
+            None => break,
+        }
+    }
+
+    write_log!(
+        LOG,
+        "Expected line: {} (visits = {}, win rate = {:.3})",
+        format_line(&line),
+        nodes[best].visits,
+        nodes[best].wins / nodes[best].visits as f64
+    );
+    write_log!(DEBUG, "Stats: {}", stats);
+    for &child in &nodes[0].children {
+        write_log!(
+            DEBUG,
+            "{}: visits = {}, win rate = {:.3}",
+            to_notation(nodes[child].view),
+            nodes[child].visits,
+            nodes[child].wins / nodes[child].visits as f64
+        );
+    }
+
+    SearchResult {
+        best: Some(nodes[best].view),
+        score: None,
+        stats,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{board::new_board, proto::Color};
+    use std::time::Duration;
+
+    fn options() -> MctsOptions {
+        MctsOptions {
+            exploration: DEFAULT_EXPLORATION,
+            heuristic: false,
+        }
+    }
+
+    fn search(board: Board, duration: Duration, options: &MctsOptions) -> SearchResult {
+        let limit = TimeLimit::fixed(duration);
+        select_best_move(
+            board,
+            limit,
+            options,
+            &mut Rng::new(0),
+            &AtomicBool::new(false),
+        )
+    }
+
+    ///
+    /// Returns the first position of random games with the empty squares which satisfies `accept`.
+    ///
+    fn find_position(empties: i32, accept: impl Fn(Board) -> bool) -> Board {
+        let mut rng = Rng::new(7);
+        loop {
+            let mut board = new_board(&Color::Black);
+            while 64 - popcnt64!(board.player | board.opponent) > empties {
+                let valid = get_valid_moves(board.player, board.opponent);
+                if valid == 0 && get_valid_moves(board.opponent, board.player) == 0 {
+                    break;
+                }
+                board = play(board, if valid == 0 { PASS } else { rng.pick(valid) });
+            }
+            if 64 - popcnt64!(board.player | board.opponent) == empties && accept(board) {
+                return board;
+            }
+        }
+    }
+
+    #[test]
+    fn test_trivial_moves() {
+        let duration = Duration::from_millis(10);
+        let board = find_position(1, |board| {
+            get_valid_moves(board.player, board.opponent) == 0
+                && get_valid_moves(board.opponent, board.player) != 0
+        });
+        assert_eq!(search(board, duration, &options()).best, None);
+
+        let board = find_position(1, |board| {
+            popcnt64!(get_valid_moves(board.player, board.opponent)) == 1
+        });
+        let valid = get_valid_moves(board.player, board.opponent);
+        assert_eq!(search(board, duration, &options()).best, Some(valid));
+    }
+
+    #[test]
+    fn test_rollout() {
+        let mut rng = Rng::new(0);
+
+        // The game is over on the full board.
+        let full = find_position(0, |_| true);
+        assert_eq!(rollout(full, false, &mut rng), result(full));
+
+        // The side to move passes and the opponent fills the last square.
+        let board = find_position(1, |board| {
+            get_valid_moves(board.player, board.opponent) == 0
+                && get_valid_moves(board.opponent, board.player) != 0
+        });
+        let last = get_valid_moves(board.opponent, board.player);
+        let end = play(play(board, PASS), last);
+        assert_eq!(rollout(board, true, &mut rng), result(end));
+        assert!(Node::new(board, PASS, None).pass);
+        assert!(!Node::new(full, PASS, None).is_expandable());
+    }
+
+    #[test]
+    fn test_pass_in_tree() {
+        // One of the moves makes the opponent pass without ending the game.
+        let board = find_position(4, |board| {
+            let mut valid = get_valid_moves(board.player, board.opponent);
+            if popcnt64!(valid) < 2 {
+                return false;
+            }
+            while valid != 0 {
+                let view = 1 << tzcnt64!(valid);
+                valid ^= view;
+                if Node::new(play(board, view), view, None).pass {
+                    return true;
+                }
+            }
+            false
+        });
+        let result = search(board, Duration::from_millis(20), &options());
+        assert_ne!(
+            result.best.unwrap() & get_valid_moves(board.player, board.opponent),
+            0
+        );
+        assert!(result.stats.nodes > 0);
+    }
+
+    ///
+    /// Returns the result for the side to move after the move with both sides playing the only
+    /// sequence left, if the game is decided by then.
+    ///
+    fn forced_result(board: Board, view: BoardView) -> Option<f64> {
+        let mut board = play(board, view);
+        let mut same_side = false;
+        loop {
+            let valid = get_valid_moves(board.player, board.opponent);
+            let view = if valid == 0 {
+                if get_valid_moves(board.opponent, board.player) == 0 {
+                    let score = result(board);
+                    return Some(if same_side { score } else { 1.0 - score });
+                }
+                PASS
+            } else if popcnt64!(valid) == 1 {
+                valid
+            } else {
+                return None;
+            };
+            board = play(board, view);
+            same_side = !same_side;
+        }
+    }
+
+    #[test]
+    fn test_winning_move() {
+        // Two moves whose games are decided: one wins and the other loses.
+        let board = find_position(2, |board| {
+            let valid = get_valid_moves(board.player, board.opponent);
+            if popcnt64!(valid) != 2 {
+                return false;
+            }
+            let first = 1 << tzcnt64!(valid);
+            let results = (
+                forced_result(board, first),
+                forced_result(board, valid ^ first),
+            );
+            matches!(results, (Some(a), Some(b)) if (a - b).abs() == 1.0)
+        });
+        let valid = get_valid_moves(board.player, board.opponent);
+        let first = 1 << tzcnt64!(valid);
+        let winning = if forced_result(board, first) == Some(1.0) {
+            first
+        } else {
+            valid ^ first
+        };
+
+        for heuristic in [false, true] {
+            let options = MctsOptions {
+                heuristic,
+                ..options()
+            };
+            let result = search(board, Duration::from_millis(20), &options);
+            assert_eq!(result.best, Some(winning));
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let board = new_board(&Color::Black);
+        let hour = Duration::from_secs(3600);
+
+        // The hard limit stops the search even if the soft one is far away.
+        let start = Instant::now();
+        let limit = TimeLimit {
+            soft: hour,
+            hard: Duration::from_millis(20),
+        };
+        let result = select_best_move(
+            board,
+            limit,
+            &options(),
+            &mut Rng::new(0),
+            &AtomicBool::new(false),
+        );
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert_ne!(
+            result.best.unwrap() & get_valid_moves(board.player, board.opponent),
+            0
+        );
+
+        // An aborted search stops after the first simulation, and the seed decides its move.
+        let mut agent = MctsAgent::new(options(), 1);
+        agent.abort_handle().unwrap().store(true, Ordering::Relaxed);
+        let result = agent.choose_move(board, TimeLimit::fixed(hour));
+        assert_eq!(result.stats.nodes, 1);
+        let mut same = MctsAgent::new(options(), 1);
+        same.abort.store(true, Ordering::Relaxed);
+        assert_eq!(
+            same.choose_move(board, TimeLimit::fixed(hour)).best,
+            result.best
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//
+// A compact binary format of the opening book, which is embedded into the executable.
+// Moves are looked up by a binary search on the sorted boards without building a table.
+//
+// All the numbers are little endian.
+//  header:    magic `RINEEBK2`, the number of positions (u32), the number of moves (u32)
+//  positions: the canonical board as the discs of the side to move and of the opponent (u64 each),
+//             the index of the first move (u32), the number of moves (u32), sorted by the board
+//  moves:     square (u8), 1 if the value exists (u8), reserved (u16), games (u32),
+//             score (f32), value (i32), in the orientation of the canonical board
+//
+
+use std::borrow::Cow;
+
+use crate::{
+    board::{canonicalize, untransform, Board},
+    book::{Book, BookMove, OpeningBook},
+    proto::Error,
+    tzcnt64,
+};
+
+pub const MAGIC: &[u8; 8] = b"RINEEBK2";
+const HEADER_SIZE: usize = 16;
+const POSITION_SIZE: usize = 24;
+const MOVE_SIZE: usize = 16;
+
+///
+/// The book converted from `preprocessed.txt` by `rinee book pack`.
+///
+static EMBEDDED: &[u8] = include_bytes!("../book.bin");
+
+///
+/// Converts the book into the binary format.
+///
+pub fn pack(book: &Book) -> Vec<u8> {
+    let mut positions: Vec<(&Board, &Vec<BookMove>)> = book.iter().collect();
+    positions.sort_by_key(|(board, _)| (board.player, board.opponent));
+
+    let moves: usize = positions.iter().map(|(_, moves)| moves.len()).sum();
+    let mut bytes =
+        Vec::with_capacity(HEADER_SIZE + positions.len() * POSITION_SIZE + moves * MOVE_SIZE);
+    bytes.extend(MAGIC);
+    bytes.extend((positions.len() as u32).to_le_bytes());
+    bytes.extend((moves as u32).to_le_bytes());
+
+    let mut first = 0u32;
+    for (board, moves) in &positions {
+        bytes.extend(board.player.to_le_bytes());
+        bytes.extend(board.opponent.to_le_bytes());
+        bytes.extend(first.to_le_bytes());
+        bytes.extend((moves.len() as u32).to_le_bytes());
+        first += moves.len() as u32;
+    }
+    for (_, moves) in &positions {
+        let mut moves = moves.to_vec();
+        moves.sort_by_key(|mv| mv.view);
+        for mv in moves {
+            bytes.push(tzcnt64!(mv.view) as u8);
+            bytes.push(mv.value.is_some() as u8);
+            bytes.extend([0; 2]);
+            bytes.extend(mv.games.to_le_bytes());
+            bytes.extend((mv.score as f32).to_le_bytes());
+            bytes.extend(mv.value.unwrap_or_default().to_le_bytes());
+        }
+    }
+    bytes
+}
+
+pub struct PackedBook {
+    bytes: Cow<'static, [u8]>,
+    positions: usize,
+}
+
+impl PackedBook {
+    pub fn new(bytes: Cow<'static, [u8]>) -> Result<Self, Error> {
+        if bytes.len() < HEADER_SIZE || &bytes[..8] != MAGIC {
+            return Err(Error::Parser);
+        }
+        let positions = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        let moves = u32::from_le_bytes(bytes[12..16].try_into().unwrap()) as usize;
+        if bytes.len() != HEADER_SIZE + positions * POSITION_SIZE + moves * MOVE_SIZE {
+            return Err(Error::Parser);
+        }
+
+        let book = PackedBook { bytes, positions };
+        for i in 0..positions {
+            let (_, first, count) = book.position(i);
+            if first + count > moves {
+                return Err(Error::Parser);
+            }
+        }
+        Ok(book)
+    }
+
+    pub fn embedded() -> Self {
+        PackedBook::new(Cow::Borrowed(EMBEDDED)).expect("the embedded book is valid")
+    }
+
+    ///
+    /// Converts the book back into the one in memory.
+    ///
+    pub fn unpack(&self) -> Book {
+        let mut book = Book::default();
+        for i in 0..self.positions {
+            let (board, first, count) = self.position(i);
+            for mv in (first..first + count).map(|i| self.book_move(i)) {
+                book.add(board, mv.view, mv.games, mv.score);
+                if let Some(value) = mv.value {
+                    book.set_value(board, mv.view, value);
+                }
+            }
+        }
+        book
+    }
+
+    fn u32_at(&self, offset: usize) -> u32 {
+        u32::from_le_bytes(self.bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(&self, offset: usize) -> u64 {
+        u64::from_le_bytes(self.bytes[offset..offset + 8].try_into().unwrap())
+    }
+
+    ///
+    /// Returns the canonical board, the index of the first move and the number of moves.
+    ///
+    fn position(&self, i: usize) -> (Board, usize, usize) {
+        let offset = HEADER_SIZE + i * POSITION_SIZE;
+        let board = Board {
+            player: self.u64_at(offset),
+            opponent: self.u64_at(offset + 8),
+        };
+        (
+            board,
+            self.u32_at(offset + 16) as usize,
+            self.u32_at(offset + 20) as usize,
+        )
+    }
+
+    fn book_move(&self, i: usize) -> BookMove {
+        let offset = HEADER_SIZE + self.positions * POSITION_SIZE + i * MOVE_SIZE;
+        let record = &self.bytes[offset..offset + MOVE_SIZE];
+        BookMove {
+            view: 1 << (record[0] & 63),
+            games: self.u32_at(offset + 4),
+            score: f32::from_le_bytes(record[8..12].try_into().unwrap()) as f64,
+            value: if record[1] != 0 {
+                Some(self.u32_at(offset + 12) as i32)
+            } else {
+                None
+            },
+        }
+    }
+}
+
+impl OpeningBook for PackedBook {
+    fn len(&self) -> usize {
+        self.positions
+    }
+
+    fn lookup(&self, board: Board) -> Vec<BookMove> {
+        let (canonical, symmetry) = canonicalize(board);
+        let key = |board: Board| (board.player, board.opponent);
+
+        let (mut low, mut high) = (0, self.positions);
+        while low < high {
+            let mid = (low + high) / 2;
+            if key(self.position(mid).0) < key(canonical) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        if low == self.positions || self.position(low).0 != canonical {
+            return Vec::new();
+        }
+
+        let (_, first, count) = self.position(low);
+        (first..first + count)
+            .map(|i| {
+                let mv = self.book_move(i);
+                BookMove {
+                    view: untransform(mv.view, symmetry),
+                    ..mv
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        board::from_notation,
+        book::{replay, DEFAULT_BOOK_FILE},
+    };
+
+    #[test]
+    fn test_pack() {
+        let mut book = Book::default();
+        let board = replay("F5D6").unwrap();
+        book.add(board, from_notation("C3").unwrap(), 3, 2.5);
+        book.add(board, from_notation("C5").unwrap(), 1, -1.0);
+
+        let packed = PackedBook::new(Cow::Owned(pack(&book))).unwrap();
+        assert_eq!(packed.len(), 1);
+        let mut moves = packed.lookup(board);
+        moves.sort_by_key(|mv| mv.view);
+        let mut expected = book.lookup(board);
+        expected.sort_by_key(|mv| mv.view);
+        assert_eq!(moves, expected);
+        assert!(packed.lookup(replay("F5").unwrap()).is_empty());
+        assert_eq!(packed.unpack().to_text(), book.to_text());
+
+        assert!(PackedBook::new(Cow::Owned(pack(&book)[..20].to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_embedded() {
+        let book = Book::load(DEFAULT_BOOK_FILE).unwrap();
+        let embedded = PackedBook::embedded();
+        assert_eq!(embedded.len(), book.len());
+        for history in ["F5", "F5D6", "C4E3", "F5F6E6"] {
+            let board = replay(history).unwrap();
+            assert_eq!(
+                embedded.best_move(board).map(|mv| mv.view),
+                book.best_move(board).map(|mv| mv.view)
+            );
+        }
+    }
+}
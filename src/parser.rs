@@ -29,8 +29,8 @@ pub fn parse_request(req: &str) -> Result<Request, Error> {
             "GIVEUP" => Ok(Request::GiveUp),
             mov => {
                 let mut mov = mov.chars();
-                let x = mov.next().ok_or(Error::Parser)? as u8 - 'A' as u8;
-                let y = mov.next().ok_or(Error::Parser)? as u8 - '1' as u8;
+                let x = mov.next().ok_or(Error::Parser)? as u8 - b'A';
+                let y = mov.next().ok_or(Error::Parser)? as u8 - b'1';
                 Ok(Request::Move { x, y })
             }
         },
@@ -0,0 +1,277 @@
+//
+// Games between a person and the engine in the terminal.
+//
+
+use std::{
+    io::{BufRead, Write},
+    time::Duration,
+};
+
+use colored::{Color as TextColor, Colorize};
+
+use crate::{
+    agent::{select_best_move, Agent, SearchOptions},
+    board::{
+        from_notation, get_pos, get_valid_moves, new_board, put, to_notation, Board, BoardView,
+        PASS,
+    },
+    clock::TimeLimit,
+    popcnt64,
+    proto::{Color, Error, GameResult},
+};
+
+///
+/// Renders the board like `DebugBoard` with coordinates and colors.
+/// `+` marks the legal moves of the side to move and the last move is underlined.
+///
+pub fn render(board: Board, to_move: Color, last: Option<BoardView>) -> String {
+    let (black, white) = match to_move {
+        Color::Black => (board.player, board.opponent),
+        Color::White => (board.opponent, board.player),
+    };
+    let valid = get_valid_moves(board.player, board.opponent);
+
+    let mut text = String::from("  A B C D E F G H\n");
+    for y in 0..8 {
+        text += &format!("{}", y + 1);
+        for x in 0..8 {
+            let pos = get_pos(x, y);
+            let cell = if black & pos != 0 {
+                "B".bold().color(TextColor::BrightBlue)
+            } else if white & pos != 0 {
+                "W".bold().color(TextColor::BrightYellow)
+            } else if valid & pos != 0 {
+                "+".color(TextColor::Green)
+            } else {
+                "-".dimmed()
+            };
+            let cell = if last == Some(pos) {
+                cell.underline()
+            } else {
+                cell
+            };
+            text += &format!(" {}", cell);
+        }
+        text.push('\n');
+    }
+    text += &format!(
+        "Black {} - {} White, {} to move",
+        popcnt64!(black),
+        popcnt64!(white),
+        to_move
+    );
+    text
+}
+
+pub struct PlayOptions {
+    ///
+    /// The color of the person.
+    ///
+    pub color: Color,
+    ///
+    /// The time for the engine to think on a move and for a hint.
+    ///
+    pub time: Duration,
+    ///
+    /// The search giving hints.
+    ///
+    pub hint: SearchOptions,
+}
+
+///
+/// A state of the game. The board is from the viewpoint of the side to move.
+///
+#[derive(Clone, Copy)]
+struct State {
+    board: Board,
+    to_move: Color,
+    last: Option<BoardView>,
+}
+
+impl State {
+    fn play(&self, view: BoardView) -> State {
+        let mut board = self.board;
+        if view != PASS {
+            put(view, &mut board.player, &mut board.opponent);
+        }
+        State {
+            board: Board {
+                player: board.opponent,
+                opponent: board.player,
+            },
+            to_move: match self.to_move {
+                Color::Black => Color::White,
+                Color::White => Color::Black,
+            },
+            last: Some(view).filter(|&view| view != PASS),
+        }
+    }
+
+    fn is_over(&self) -> bool {
+        get_valid_moves(self.board.player, self.board.opponent) == 0
+            && get_valid_moves(self.board.opponent, self.board.player) == 0
+    }
+}
+
+///
+/// Plays a game reading the commands of the person from `input`.
+/// The commands are a move like `C4`, `pass`, `undo`, `hint` and `quit`.
+///
+pub fn play<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    agent: &mut dyn Agent,
+    options: &PlayOptions,
+) -> Result<(), Error> {
+    let engine_color = match options.color {
+        Color::Black => Color::White,
+        Color::White => Color::Black,
+    };
+    agent.new_game(&engine_color);
+
+    let mut state = State {
+        board: new_board(&Color::Black),
+        to_move: Color::Black,
+        last: None,
+    };
+    // The states before the moves of the person.
+    let mut undo: Vec<State> = Vec::new();
+
+    while !state.is_over() {
+        let valid = get_valid_moves(state.board.player, state.board.opponent);
+
+        if state.to_move == engine_color {
+            let view = agent
+                .choose_move(state.board, TimeLimit::fixed(options.time))
+                .best
+                .filter(|&view| view & valid != 0)
+                .unwrap_or(PASS);
+            writeln!(output, "Rinee plays {}.", to_notation(view))?;
+            let next = state.play(view);
+            agent.ponder(Board {
+                player: next.board.opponent,
+                opponent: next.board.player,
+            });
+            state = next;
+            continue;
+        }
+
+        writeln!(output, "{}", render(state.board, state.to_move, state.last))?;
+        if valid == 0 {
+            writeln!(output, "You have no legal move and have to pass.")?;
+        }
+        write!(output, "Your move (C4, pass, undo, hint or quit): ")?;
+        output.flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let command = line.trim().to_ascii_lowercase();
+        match command.as_str() {
+            "quit" => return Ok(()),
+            "undo" => match undo.pop() {
+                Some(previous) => {
+                    agent.stop_pondering();
+                    agent.new_game(&engine_color);
+                    state = previous;
+                }
+                None => writeln!(output, "Nothing to undo.")?,
+            },
+            "hint" => {
+                let result = select_best_move(
+                    state.board,
+                    TimeLimit::fixed(options.time),
+                    &options.hint,
+                    &[],
+                );
+                writeln!(output, "Hint: {}", to_notation(result.best.unwrap_or(PASS)))?;
+            }
+            _ => {
+                let view = match from_notation(&command) {
+                    Some(PASS) if valid == 0 => PASS,
+                    Some(view) if view & valid != 0 => view,
+                    _ => {
+                        writeln!(output, "\"{}\" is not a legal move.", line.trim())?;
+                        continue;
+                    }
+                };
+                agent.observe(
+                    Board {
+                        player: state.board.opponent,
+                        opponent: state.board.player,
+                    },
+                    view,
+                );
+                undo.push(state);
+                state = state.play(view);
+            }
+        }
+    }
+
+    agent.stop_pondering();
+    writeln!(output, "{}", render(state.board, state.to_move, state.last))?;
+    let (mine, theirs) = if state.to_move == options.color {
+        (
+            popcnt64!(state.board.player),
+            popcnt64!(state.board.opponent),
+        )
+    } else {
+        (
+            popcnt64!(state.board.opponent),
+            popcnt64!(state.board.player),
+        )
+    };
+    let result = if mine > theirs {
+        writeln!(output, "You win by {} - {}.", mine, theirs)?;
+        GameResult::Lose
+    } else if mine < theirs {
+        writeln!(output, "Rinee wins by {} - {}.", theirs, mine)?;
+        GameResult::Win
+    } else {
+        writeln!(output, "Draw.")?;
+        GameResult::Tie
+    };
+    agent.game_end(&result);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::baseline::GreedyAgent;
+
+    fn run(color: Color, commands: &str) -> String {
+        let mut output = Vec::new();
+        let options = PlayOptions {
+            color,
+            time: Duration::from_millis(10),
+            hint: SearchOptions::default(),
+        };
+        play(
+            &mut commands.as_bytes(),
+            &mut output,
+            &mut GreedyAgent,
+            &options,
+        )
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_play() {
+        colored::control::set_override(false);
+
+        let output = run(Color::Black, "a1\nf5\nundo\nundo\nhint\nquit\n");
+        assert!(output.contains("\"a1\" is not a legal move."));
+        assert!(output.contains("Rinee plays"));
+        assert!(output.contains("Nothing to undo."));
+        assert!(output.contains("Hint: "));
+        // The board is back to the initial one after the undo.
+        assert!(output.ends_with("  A B C D E F G H\n1 - - - - - - - -\n2 - - - - - - - -\n3 - - - + - - - -\n4 - - + W B - - -\n5 - - - B W + - -\n6 - - - - + - - -\n7 - - - - - - - -\n8 - - - - - - - -\nBlack 2 - 2 White, Black to move\nYour move (C4, pass, undo, hint or quit): "));
+
+        // The engine moves first as black.
+        let output = run(Color::White, "quit\n");
+        assert!(output.starts_with("Rinee plays"));
+    }
+}
@@ -0,0 +1,155 @@
+//
+// Thinking on the opponent's time.
+// Every reply of the opponent is searched in turn with the same depth as `select_best_move` would,
+// so that the results on the reply actually played can be reused.
+//
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+use crate::{
+    agent::{Choice, SearchOptions, Searcher, INF},
+    board::{get_valid_moves, put, Board},
+    popcnt64, tzcnt64, write_log,
+};
+
+pub struct Ponder {
+    interrupt: Arc<AtomicBool>,
+    handle: JoinHandle<HashMap<Board, Vec<Choice>>>,
+    start: Instant,
+}
+
+impl Ponder {
+    ///
+    /// Starts pondering on the board where the opponent is to move.
+    ///
+    pub fn start(board: Board, options: &SearchOptions) -> Self {
+        let interrupt = Arc::new(AtomicBool::new(false));
+        let searcher = Searcher::new(
+            interrupt.clone(),
+            options.probcut.clone(),
+            options.tt.clone(),
+        );
+        Ponder {
+            interrupt,
+            handle: thread::spawn(move || ponder(searcher, board)),
+            start: Instant::now(),
+        }
+    }
+
+    ///
+    /// Aborts pondering and returns the results of every move on the boards after the replies.
+    ///
+    pub fn stop(self) -> HashMap<Board, Vec<Choice>> {
+        self.interrupt.store(true, Ordering::Relaxed);
+        let results = self.handle.join().unwrap_or_default();
+        write_log!(
+            DEBUG,
+            "Pondered for {}ms on {} replies.",
+            self.start.elapsed().as_millis(),
+            results.len()
+        );
+        results
+    }
+}
+
+fn ponder(mut searcher: Searcher, board: Board) -> HashMap<Board, Vec<Choice>> {
+    let mut results: HashMap<Board, Vec<Choice>> = HashMap::new();
+
+    // Boards after the replies of the opponent.
+    let mut roots = Vec::new();
+    let mut replies = get_valid_moves(board.opponent, board.player);
+    while replies != 0 {
+        let view = 1 << tzcnt64!(replies);
+        replies ^= view;
+
+        let mut root = board;
+        put(view, &mut root.opponent, &mut root.player);
+        // There is nothing to think about with a single move.
+        if popcnt64!(get_valid_moves(root.player, root.opponent)) > 1 {
+            roots.push(root);
+        }
+    }
+
+    let mut pv = Vec::new();
+    for depth in 5..60 {
+        for &root in &roots {
+            // Moves which have already been decided are no longer deepened.
+            if let Some(choices) = results.get(&root) {
+                let decided = choices
+                    .iter()
+                    .all(|choice| choice.score == INF || choice.score == -INF);
+                if decided {
+                    continue;
+                }
+            }
+
+            let mut choices = Vec::new();
+            let mut valid = get_valid_moves(root.player, root.opponent);
+            while valid != 0 {
+                let view = 1 << tzcnt64!(valid);
+                valid ^= view;
+
+                let mut next = root;
+                put(view, &mut next.player, &mut next.opponent);
+                match searcher.alpha_beta(next, false, depth, i32::MIN, i32::MAX, &mut pv) {
+                    Ok(score) => {
+                        pv.insert(0, view);
+                        choices.push(Choice {
+                            view,
+                            depth,
+                            score,
+                            pv: pv.clone(),
+                        });
+                    }
+                    Err(_) => return results,
+                }
+            }
+
+            results.insert(root, choices);
+        }
+    }
+
+    results
+}
+
+#[cfg(test)]
+mod test {
+    use std::{thread::sleep, time::Duration};
+
+    use super::*;
+    use crate::bench::bench_positions;
+
+    #[test]
+    fn test_stop() {
+        // The opponent is to move on the boards.
+        for board in bench_positions(4, 2) {
+            let board = Board {
+                player: board.opponent,
+                opponent: board.player,
+            };
+            let ponder = Ponder::start(board, &SearchOptions::default());
+            sleep(Duration::from_millis(100));
+
+            // The search to depth 60 is aborted and the thread is joined.
+            let start = Instant::now();
+            let results = ponder.stop();
+            assert!(start.elapsed() < Duration::from_secs(1));
+
+            for (root, choices) in results {
+                assert_eq!(
+                    choices.iter().fold(0, |views, choice| views | choice.view),
+                    get_valid_moves(root.player, root.opponent)
+                );
+                assert!(choices.iter().all(|choice| choice.depth >= 5));
+            }
+        }
+    }
+}
@@ -0,0 +1,404 @@
+//
+// Multi-ProbCut.
+//
+// References:
+//  M. Buro, "Experiments with Multi-ProbCut and a New High-Quality Evaluation Function for Othello" (1997)
+//
+
+use std::{
+    sync::{atomic::AtomicBool, Arc},
+    thread,
+};
+
+use crate::{
+    agent::{best_move_at_depth, Searcher, INF},
+    board::{get_valid_moves, new_board, put, Board},
+    popcnt64,
+    proto::{Color, Error},
+    util::Rng,
+    write_log, Args,
+};
+
+///
+/// A linear model predicting the `depth` search from the `shallow` one:
+/// `deep = a * shallow + b + e` where `e ~ N(0, sigma^2)`.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cut {
+    pub depth: u8,
+    pub shallow: u8,
+    pub a: f64,
+    pub b: f64,
+    pub sigma: f64,
+}
+
+pub struct ProbCut {
+    cuts: Vec<Cut>,
+    ///
+    /// How many sigmas the prediction must be away from the window to cut.
+    ///
+    pub threshold: f64,
+}
+
+pub const DEFAULT_THRESHOLD: f64 = 1.5;
+
+///
+/// Fitted by `rinee mpc fit --games 64 --max-depth 8 --seed 0`.
+///
+static DEFAULT_CUTS: &[Cut] = &[
+    Cut {
+        depth: 3,
+        shallow: 1,
+        a: 1.1295,
+        b: 17.41,
+        sigma: 107.99,
+    },
+    Cut {
+        depth: 4,
+        shallow: 2,
+        a: 1.1291,
+        b: 11.56,
+        sigma: 109.74,
+    },
+    Cut {
+        depth: 5,
+        shallow: 1,
+        a: 1.2599,
+        b: 23.41,
+        sigma: 177.91,
+    },
+    Cut {
+        depth: 5,
+        shallow: 3,
+        a: 1.1300,
+        b: 3.29,
+        sigma: 101.26,
+    },
+    Cut {
+        depth: 6,
+        shallow: 2,
+        a: 1.2453,
+        b: 17.20,
+        sigma: 195.12,
+    },
+    Cut {
+        depth: 6,
+        shallow: 4,
+        a: 1.1183,
+        b: 4.48,
+        sigma: 123.54,
+    },
+    Cut {
+        depth: 7,
+        shallow: 1,
+        a: 1.3575,
+        b: 37.83,
+        sigma: 276.25,
+    },
+    Cut {
+        depth: 7,
+        shallow: 3,
+        a: 1.2365,
+        b: 15.23,
+        sigma: 204.21,
+    },
+    Cut {
+        depth: 8,
+        shallow: 2,
+        a: 1.3708,
+        b: 24.62,
+        sigma: 291.07,
+    },
+    Cut {
+        depth: 8,
+        shallow: 4,
+        a: 1.2467,
+        b: 10.71,
+        sigma: 217.93,
+    },
+];
+
+impl Default for ProbCut {
+    fn default() -> Self {
+        ProbCut {
+            cuts: DEFAULT_CUTS.to_vec(),
+            threshold: DEFAULT_THRESHOLD,
+        }
+    }
+}
+
+impl ProbCut {
+    ///
+    /// Returns the cuts available at the depth, the cheapest first.
+    ///
+    pub fn cuts(&self, depth: u8) -> impl Iterator<Item = &Cut> {
+        self.cuts.iter().filter(move |cut| cut.depth == depth)
+    }
+
+    ///
+    /// Parses lines of `depth shallow a b sigma`. `#` starts a comment.
+    ///
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut cuts = Vec::new();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut iter = line.split_whitespace();
+            let mut next = || {
+                iter.next()
+                    .ok_or(Error::ParserWithMessage(line.to_string()))
+            };
+            let depth = next()?.parse().map_err(|_| Error::Parser)?;
+            let shallow = next()?.parse().map_err(|_| Error::Parser)?;
+            let a = next()?.parse().map_err(|_| Error::Parser)?;
+            let b = next()?.parse().map_err(|_| Error::Parser)?;
+            let sigma = next()?.parse().map_err(|_| Error::Parser)?;
+
+            if shallow >= depth || a <= 0.0 {
+                return Err(Error::ParserWithMessage(line.to_string()));
+            }
+            cuts.push(Cut {
+                depth,
+                shallow,
+                a,
+                b,
+                sigma,
+            });
+        }
+        cuts.sort_by_key(|cut| (cut.depth, cut.shallow));
+
+        Ok(ProbCut {
+            cuts,
+            threshold: DEFAULT_THRESHOLD,
+        })
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# depth shallow a b sigma\n");
+        for cut in &self.cuts {
+            text += &format!(
+                "{} {} {:.4} {:.2} {:.2}\n",
+                cut.depth, cut.shallow, cut.a, cut.b, cut.sigma
+            );
+        }
+        text
+    }
+}
+
+///
+/// Builds the parameters specified by the command line.
+///
+pub fn load_probcut(args: &Args) -> Result<Option<Arc<ProbCut>>, Error> {
+    if args.no_mpc {
+        return Ok(None);
+    }
+
+    let mut probcut = match &args.mpc {
+        Some(path) => ProbCut::parse(&std::fs::read_to_string(path)?)?,
+        None => ProbCut::default(),
+    };
+    probcut.threshold = args.mpc_threshold;
+
+    Ok(Some(Arc::new(probcut)))
+}
+
+///
+/// Shallow depths used for the deep one.
+/// They have the same parity as the deep one since the evaluation depends on it.
+///
+fn shallow_depths(depth: u8) -> Vec<u8> {
+    let smallest = 2 - depth % 2;
+    let largest = (depth / 2 + 1 - (depth / 2 + 1 + depth) % 2).max(smallest);
+    if smallest == largest {
+        vec![smallest]
+    } else {
+        vec![smallest, largest]
+    }
+}
+
+///
+/// Plays games against itself and collects positions from the viewpoint of the side to move.
+///
+fn self_play_positions(games: usize, max_depth: u8, rng: &mut Rng) -> Vec<Board> {
+    // Opening moves are random so that the games differ.
+    const RANDOM_PLIES: usize = 8;
+    const RANDOM_RATE: f64 = 0.1;
+
+    let mut searcher = Searcher::new(Arc::new(AtomicBool::new(false)), None, None);
+    let mut positions = Vec::new();
+
+    for _ in 0..games {
+        let mut board = new_board(&Color::Black);
+        let mut ply = 0;
+
+        loop {
+            let valid = get_valid_moves(board.player, board.opponent);
+            if valid == 0 {
+                if get_valid_moves(board.opponent, board.player) == 0 {
+                    break;
+                }
+            } else {
+                let empties = 64 - popcnt64!(board.player | board.opponent);
+                if ply >= RANDOM_PLIES && empties > max_depth as i32 {
+                    positions.push(board);
+                }
+
+                let view = if ply < RANDOM_PLIES || rng.next_f64() < RANDOM_RATE {
+                    rng.pick(valid)
+                } else {
+                    match best_move_at_depth(&mut searcher, board, 2) {
+                        Ok(Some(choice)) => choice.view,
+                        _ => rng.pick(valid),
+                    }
+                };
+                put(view, &mut board.player, &mut board.opponent);
+                ply += 1;
+            }
+            board = Board {
+                player: board.opponent,
+                opponent: board.player,
+            };
+        }
+    }
+
+    positions
+}
+
+///
+/// Fits the parameters with the least squares method.
+///
+pub fn fit(games: usize, max_depth: u8, seed: u64) -> ProbCut {
+    let mut rng = Rng::new(seed);
+    let positions = self_play_positions(games, max_depth, &mut rng);
+    write_log!(LOG, "Collected {} positions.", positions.len());
+
+    // Scores of every position at every depth.
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = positions.len().div_ceil(workers).max(1);
+    let scores: Vec<Vec<i32>> = thread::scope(|scope| {
+        let handles: Vec<_> = positions
+            .chunks(chunk)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    let mut searcher = Searcher::new(Arc::new(AtomicBool::new(false)), None, None);
+                    let mut pv = Vec::new();
+                    chunk
+                        .iter()
+                        .map(|&board| {
+                            (0..=max_depth)
+                                .map(|depth| {
+                                    searcher
+                                        .alpha_beta(board, true, depth, -INF, INF, &mut pv)
+                                        .unwrap()
+                                })
+                                .collect()
+                        })
+                        .collect::<Vec<Vec<i32>>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    let mut cuts = Vec::new();
+    for depth in 3..=max_depth {
+        for shallow in shallow_depths(depth) {
+            let samples: Vec<(f64, f64)> = scores
+                .iter()
+                .map(|score| (score[shallow as usize], score[depth as usize]))
+                .filter(|(x, y)| x.abs() < INF && y.abs() < INF)
+                .map(|(x, y)| (x as f64, y as f64))
+                .collect();
+            if samples.len() < 2 {
+                continue;
+            }
+
+            let n = samples.len() as f64;
+            let mean_x = samples.iter().map(|(x, _)| x).sum::<f64>() / n;
+            let mean_y = samples.iter().map(|(_, y)| y).sum::<f64>() / n;
+            let cov = samples
+                .iter()
+                .map(|(x, y)| (x - mean_x) * (y - mean_y))
+                .sum::<f64>();
+            let var = samples
+                .iter()
+                .map(|(x, _)| (x - mean_x).powi(2))
+                .sum::<f64>();
+            if var == 0.0 || cov <= 0.0 {
+                continue;
+            }
+
+            let a = cov / var;
+            let b = mean_y - a * mean_x;
+            let sigma = (samples
+                .iter()
+                .map(|(x, y)| (y - a * x - b).powi(2))
+                .sum::<f64>()
+                / n)
+                .sqrt();
+
+            write_log!(
+                LOG,
+                "depth = {}, shallow = {}: a = {:.4}, b = {:.2}, sigma = {:.2} ({} samples)",
+                depth,
+                shallow,
+                a,
+                b,
+                sigma,
+                samples.len()
+            );
+            cuts.push(Cut {
+                depth,
+                shallow,
+                a,
+                b,
+                sigma,
+            });
+        }
+    }
+
+    ProbCut {
+        cuts,
+        threshold: DEFAULT_THRESHOLD,
+    }
+}
+
+///
+/// Fits the parameters and writes them to the file, which `--mpc` reads.
+///
+pub fn run_fit(games: usize, max_depth: u8, seed: u64, output: &str) -> Result<(), Error> {
+    let probcut = fit(games, max_depth, seed);
+    std::fs::write(output, probcut.to_text())?;
+    println!("The parameters are written to {}.", output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_shallow_depths() {
+        assert_eq!(shallow_depths(3), vec![1]);
+        assert_eq!(shallow_depths(4), vec![2]);
+        assert_eq!(shallow_depths(5), vec![1, 3]);
+        assert_eq!(shallow_depths(8), vec![2, 4]);
+    }
+
+    #[test]
+    fn test_parse() {
+        let probcut = ProbCut::parse("# comment\n4 2 1.0 0.5 30.0\n3 1 0.9 -1.0 20.0\n").unwrap();
+        assert_eq!(probcut.cuts(3).count(), 1);
+        assert_eq!(probcut.cuts(4).next().unwrap().sigma, 30.0);
+        assert_eq!(
+            ProbCut::parse(&probcut.to_text()).unwrap().cuts,
+            probcut.cuts
+        );
+    }
+}
@@ -1,7 +1,12 @@
+#[derive(Debug)]
 pub enum Error {
     IO(std::io::Error),
     Parser,
     ParserWithMessage(String),
+    ///
+    /// A check which did not pass, like wrong results of a benchmark.
+    ///
+    Failed(String),
 }
 
 impl From<std::io::Error> for Error {
@@ -10,6 +15,7 @@ impl From<std::io::Error> for Error {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum GameResult {
     Win,
     Lose,
@@ -33,6 +39,7 @@ pub struct GameStat {
     pub loses: u32,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Color {
     Black,
     White,
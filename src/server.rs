@@ -0,0 +1,419 @@
+//
+// A local game server for the protocol which `parser::parse_request` consumes.
+// Two clients play a number of games in turns, swapping the colors after each game.
+//
+
+use std::{
+    io::{BufRead, BufReader, ErrorKind, Write},
+    net::{TcpListener, TcpStream},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    board::{from_notation, get_valid_moves, new_board, put, to_notation, Board, BoardView, PASS},
+    popcnt64,
+    proto::{Color, Error, GameResult},
+    write_log,
+};
+
+pub struct ServerOptions {
+    pub port: u16,
+    ///
+    /// The number of games played in the session.
+    ///
+    pub games: u32,
+    ///
+    /// The time of each player for a game in milliseconds.
+    ///
+    pub time: u64,
+}
+
+struct Client {
+    name: String,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    score: i32,
+    wins: u32,
+    loses: u32,
+}
+
+impl Client {
+    fn accept(listener: &TcpListener) -> Result<Self, Error> {
+        let (stream, addr) = listener.accept()?;
+        let mut client = Client {
+            name: String::new(),
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            score: 0,
+            wins: 0,
+            loses: 0,
+        };
+
+        let line = client.receive(None)?.unwrap_or_default();
+        match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["OPEN", name] => client.name = name.to_string(),
+            _ => return Err(Error::ParserWithMessage(line)),
+        }
+        write_log!(LOG, "{} connected from {}.", client.name, addr);
+        Ok(client)
+    }
+
+    fn send(&mut self, line: &str) -> Result<(), Error> {
+        write_log!(DEBUG, "-> {}: {}", self.name, line);
+        self.writer.write_all(format!("{}\n", line).as_bytes())?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    ///
+    /// Receives a line, or returns `None` if the time runs out.
+    ///
+    fn receive(&mut self, timeout: Option<Duration>) -> Result<Option<String>, Error> {
+        // The socket rejects a zero timeout.
+        self.reader
+            .get_ref()
+            .set_read_timeout(timeout.map(|timeout| timeout.max(Duration::from_millis(1))))?;
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => Err(Error::IO(ErrorKind::UnexpectedEof.into())),
+            Ok(_) => {
+                write_log!(DEBUG, "<- {}: {}", self.name, line.trim());
+                Ok(Some(line.trim().to_string()))
+            }
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                Ok(None)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    ///
+    /// Discards the lines which arrived after the end of the last game, like a move sent too late,
+    /// so that they are not taken for the moves of the next game.
+    ///
+    fn drain(&mut self) -> Result<(), Error> {
+        while let Some(line) = self.receive(Some(Duration::ZERO))? {
+            write_log!(LOG, "Discarded a late line of {}: {}", self.name, line);
+        }
+        Ok(())
+    }
+}
+
+enum ClientMove {
+    ///
+    /// `PASS` stands for a pass.
+    ///
+    Play(BoardView),
+    GiveUp,
+}
+
+///
+/// Parses `MOVE C4`, `MOVE PASS` or `MOVE GIVEUP` from a client.
+///
+fn parse_move(line: &str) -> Option<ClientMove> {
+    match line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["MOVE", "GIVEUP"] => Some(ClientMove::GiveUp),
+        ["MOVE", notation] => from_notation(notation).map(ClientMove::Play),
+        _ => None,
+    }
+}
+
+fn result_word(result: GameResult) -> &'static str {
+    match result {
+        GameResult::Win => "WIN",
+        GameResult::Lose => "LOSE",
+        GameResult::Tie => "TIE",
+    }
+}
+
+///
+/// Plays a game where `clients[black]` is black.
+/// Returns the disc counts of the clients, the loser by a violation or by giving up if any,
+/// and the reason.
+///
+fn play_game(
+    clients: &mut [Client; 2],
+    black: usize,
+    time: u64,
+) -> Result<([u32; 2], Option<usize>, &'static str), Error> {
+    let white = 1 - black;
+    for client in clients.iter_mut() {
+        client.drain()?;
+    }
+    let start = format!("START BLACK {} {}", clients[white].name, time);
+    clients[black].send(&start)?;
+    let start = format!("START WHITE {} {}", clients[black].name, time);
+    clients[white].send(&start)?;
+
+    // The board from the viewpoint of the side to move.
+    let mut board = new_board(&Color::Black);
+    let mut turn = black;
+    let mut remains = [time; 2];
+
+    let counts = |board: Board, turn: usize| {
+        let mut counts = [0; 2];
+        counts[turn] = popcnt64!(board.player) as u32;
+        counts[1 - turn] = popcnt64!(board.opponent) as u32;
+        counts
+    };
+
+    loop {
+        let start = Instant::now();
+        let line = clients[turn].receive(Some(Duration::from_millis(remains[turn])))?;
+        let elapsed = start.elapsed().as_millis() as u64;
+        let Some(line) = line.filter(|_| elapsed <= remains[turn]) else {
+            return Ok((counts(board, turn), Some(turn), "TIMEOUT"));
+        };
+        remains[turn] -= elapsed;
+
+        let valid = get_valid_moves(board.player, board.opponent);
+        let view = match parse_move(&line) {
+            Some(ClientMove::Play(PASS)) if valid == 0 => PASS,
+            Some(ClientMove::Play(view)) if view & valid != 0 => view,
+            Some(ClientMove::GiveUp) => {
+                write_log!(LOG, "{} gave up.", clients[turn].name);
+                clients[1 - turn].send("MOVE GIVEUP")?;
+                return Ok((counts(board, turn), Some(turn), "GIVEUP"));
+            }
+            _ => {
+                write_log!(LOG, "{} sent an illegal move: {}", clients[turn].name, line);
+                return Ok((counts(board, turn), Some(turn), "ILLEGAL_MOVE"));
+            }
+        };
+        clients[turn].send(&format!("ACK {}", remains[turn]))?;
+
+        if view != PASS {
+            put(view, &mut board.player, &mut board.opponent);
+        }
+        board = Board {
+            player: board.opponent,
+            opponent: board.player,
+        };
+        turn = 1 - turn;
+
+        if get_valid_moves(board.player, board.opponent) == 0
+            && get_valid_moves(board.opponent, board.player) == 0
+        {
+            return Ok((counts(board, turn), None, "DOUBLE_PASS"));
+        }
+        clients[turn].send(&format!("MOVE {}", to_notation(view)))?;
+    }
+}
+
+///
+/// Serves a session of games to the first two clients connecting to the listener.
+///
+pub fn serve(listener: TcpListener, options: &ServerOptions) -> Result<(), Error> {
+    let mut clients = [Client::accept(&listener)?, Client::accept(&listener)?];
+
+    for game in 0..options.games {
+        let black = game as usize % 2;
+        let (counts, violation, reason) = play_game(&mut clients, black, options.time)?;
+
+        let results = match violation {
+            Some(loser) => {
+                let mut results = [GameResult::Win; 2];
+                results[loser] = GameResult::Lose;
+                results
+            }
+            None if counts[0] > counts[1] => [GameResult::Win, GameResult::Lose],
+            None if counts[0] < counts[1] => [GameResult::Lose, GameResult::Win],
+            None => [GameResult::Tie; 2],
+        };
+        for i in 0..2 {
+            let client = &mut clients[i];
+            client.score += counts[i] as i32 - counts[1 - i] as i32;
+            match results[i] {
+                GameResult::Win => client.wins += 1,
+                GameResult::Lose => client.loses += 1,
+                GameResult::Tie => {}
+            }
+            let end = format!(
+                "END {} {} {} {}",
+                result_word(results[i]),
+                counts[i],
+                counts[1 - i],
+                reason
+            );
+            client.send(&end)?;
+        }
+
+        println!(
+            "Game {}: {} (black) {} - {} {} (white) by {}",
+            game + 1,
+            clients[black].name,
+            counts[black],
+            counts[1 - black],
+            clients[1 - black].name,
+            reason
+        );
+    }
+
+    let stats = clients
+        .iter()
+        .map(|client| {
+            format!(
+                "{} {} {} {}",
+                client.name, client.score, client.wins, client.loses
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    for client in clients.iter_mut() {
+        client.send(&format!("BYE {}", stats))?;
+    }
+    for client in &clients {
+        println!(
+            "{}: score = {}, win/lose = {}/{}",
+            client.name, client.score, client.wins, client.loses
+        );
+    }
+    Ok(())
+}
+
+pub fn run_server(options: &ServerOptions) -> Result<(), Error> {
+    let listener = TcpListener::bind(("0.0.0.0", options.port))?;
+    println!("Waiting for two clients on port {}.", options.port);
+    serve(listener, options)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{board::get_pos, parser::parse_request, proto::Request, tzcnt64};
+
+    ///
+    /// A client playing the first legal move. Returns the results of the games.
+    ///
+    fn play_first_moves(port: u16, name: &str) -> Vec<(GameResult, u8, u8)> {
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        writer
+            .write_all(format!("OPEN {}\n", name).as_bytes())
+            .unwrap();
+
+        let mut board = new_board(&Color::Black);
+        let mut results = Vec::new();
+        let play = |board: &mut Board, writer: &mut TcpStream| {
+            let valid = get_valid_moves(board.player, board.opponent);
+            let view = if valid == 0 {
+                PASS
+            } else {
+                1 << tzcnt64!(valid)
+            };
+            if view != PASS {
+                put(view, &mut board.player, &mut board.opponent);
+            }
+            writer
+                .write_all(format!("MOVE {}\n", to_notation(view)).as_bytes())
+                .unwrap();
+        };
+
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            match parse_request(&line).unwrap() {
+                Request::Start { color, .. } => {
+                    board = new_board(&color);
+                    if color == Color::Black {
+                        play(&mut board, &mut writer);
+                    }
+                }
+                Request::Move { x, y } => {
+                    put(get_pos(x, y), &mut board.opponent, &mut board.player);
+                    play(&mut board, &mut writer);
+                }
+                Request::Pass => play(&mut board, &mut writer),
+                Request::End {
+                    result,
+                    score,
+                    opponent_score,
+                    ..
+                } => results.push((result, score, opponent_score)),
+                Request::Bye { .. } => return results,
+                Request::Ack { .. } | Request::GiveUp => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_give_up() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let options = ServerOptions {
+            port,
+            games: 2,
+            time: 10000,
+        };
+        let server = std::thread::spawn(move || serve(listener, &options));
+
+        // The client accepted first plays black in the first game.
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        writer.write_all(b"OPEN giver\n").unwrap();
+        let other = std::thread::spawn(move || play_first_moves(port, "other"));
+
+        // It gives up on every move, and sends a late move after the first one,
+        // which is illegal for white after D3 in the second game.
+        let mut reasons = Vec::new();
+        let mut late = Some("MOVE F5\n");
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            match parse_request(&line).unwrap() {
+                Request::Start {
+                    color: Color::Black,
+                    ..
+                }
+                | Request::Move { .. } => {
+                    let reply = format!("MOVE GIVEUP\n{}", late.take().unwrap_or_default());
+                    writer.write_all(reply.as_bytes()).unwrap();
+                }
+                Request::End { result, reason, .. } => reasons.push((result, reason)),
+                Request::Bye { .. } => break,
+                _ => {}
+            }
+        }
+        server.join().unwrap().unwrap();
+
+        let gave_up = (GameResult::Lose, String::from("GIVEUP"));
+        assert_eq!(reasons, [gave_up.clone(), gave_up]);
+        assert!(other
+            .join()
+            .unwrap()
+            .iter()
+            .all(|&(result, _, _)| result == GameResult::Win));
+    }
+
+    #[test]
+    fn test_serve() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let options = ServerOptions {
+            port,
+            games: 2,
+            time: 10000,
+        };
+        let server = std::thread::spawn(move || serve(listener, &options));
+
+        let first = std::thread::spawn(move || play_first_moves(port, "first"));
+        let second = play_first_moves(port, "second");
+        let first = first.join().unwrap();
+        server.join().unwrap().unwrap();
+
+        assert_eq!(first.len(), 2);
+        for ((result, score, opponent_score), (other, other_score, other_opponent)) in
+            first.into_iter().zip(second)
+        {
+            assert_eq!((score, opponent_score), (other_opponent, other_score));
+            let expected = match result {
+                GameResult::Win => GameResult::Lose,
+                GameResult::Lose => GameResult::Win,
+                GameResult::Tie => GameResult::Tie,
+            };
+            assert_eq!(other, expected);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::{fs::OpenOptions, io::Write, ops::AddAssign, path::Path, time::Duration};
+
+use crate::proto::Error;
+
+///
+/// Statistics gathered during a search.
+///
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchStats {
+    pub nodes: u64,
+    ///
+    /// Nodes which have at least one move to search.
+    ///
+    pub interior_nodes: u64,
+    ///
+    /// The depth completed on every move at the root.
+    ///
+    pub depth: u8,
+    ///
+    /// The deepest ply reached from the root.
+    ///
+    pub selective_depth: u8,
+    pub cutoffs: u64,
+    ///
+    /// Cutoffs caused by the first move searched.
+    ///
+    pub first_move_cutoffs: u64,
+    pub tt_probes: u64,
+    pub tt_hits: u64,
+    pub time: Duration,
+}
+
+impl SearchStats {
+    pub fn nps(&self) -> f64 {
+        let secs = self.time.as_secs_f64();
+        if secs == 0.0 {
+            0.0
+        } else {
+            self.nodes as f64 / secs
+        }
+    }
+
+    pub fn cutoff_rate(&self) -> f64 {
+        ratio(self.cutoffs, self.interior_nodes)
+    }
+
+    pub fn first_move_cutoff_rate(&self) -> f64 {
+        ratio(self.first_move_cutoffs, self.cutoffs)
+    }
+
+    pub fn tt_hit_rate(&self) -> f64 {
+        ratio(self.tt_hits, self.tt_probes)
+    }
+
+    ///
+    /// Appends the statistics of a move to the CSV file, writing the header if the file is new.
+    ///
+    pub fn append_csv(&self, path: &str, notation: &str) -> Result<(), Error> {
+        let is_new = !Path::new(path).exists();
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if is_new {
+            writeln!(
+                file,
+                "version,move,nodes,nps,depth,selective_depth,cutoff_rate,first_move_cutoff_rate,tt_hit_rate,time_ms"
+            )?;
+        }
+        writeln!(
+            file,
+            "{},{},{},{:.0},{},{},{:.4},{:.4},{:.4},{}",
+            env!("CARGO_PKG_VERSION"),
+            notation,
+            self.nodes,
+            self.nps(),
+            self.depth,
+            self.selective_depth,
+            self.cutoff_rate(),
+            self.first_move_cutoff_rate(),
+            self.tt_hit_rate(),
+            self.time.as_millis()
+        )?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for SearchStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "nodes = {}, nps = {:.0}, depth = {}/{}, cutoff = {:.1}%, first cutoff = {:.1}%, tt hit = {:.1}%, time = {}ms",
+            self.nodes,
+            self.nps(),
+            self.depth,
+            self.selective_depth,
+            self.cutoff_rate() * 100.0,
+            self.first_move_cutoff_rate() * 100.0,
+            self.tt_hit_rate() * 100.0,
+            self.time.as_millis()
+        )
+    }
+}
+
+///
+/// Merges the counters of another search running in parallel.
+/// The completed depth is left to the caller.
+///
+impl AddAssign for SearchStats {
+    fn add_assign(&mut self, other: Self) {
+        self.nodes += other.nodes;
+        self.interior_nodes += other.interior_nodes;
+        self.selective_depth = self.selective_depth.max(other.selective_depth);
+        self.cutoffs += other.cutoffs;
+        self.first_move_cutoffs += other.first_move_cutoffs;
+        self.tt_probes += other.tt_probes;
+        self.tt_hits += other.tt_hits;
+        self.time = self.time.max(other.time);
+    }
+}
+
+fn ratio(n: u64, d: u64) -> f64 {
+    if d == 0 {
+        0.0
+    } else {
+        n as f64 / d as f64
+    }
+}
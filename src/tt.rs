@@ -0,0 +1,142 @@
+//
+// A transposition table shared by the search tasks.
+// Entries are written without locks; a torn entry is detected by the xor of its key and data.
+//
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{
+    board::{get_pos, Board, BoardView},
+    tzcnt64,
+};
+
+pub const DEFAULT_BITS: u32 = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Entry {
+    pub score: i32,
+    pub depth: u8,
+    pub bound: Bound,
+    pub best: Option<BoardView>,
+}
+
+impl Entry {
+    fn pack(&self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+        let best = match self.best {
+            Some(view) => tzcnt64!(view) + 1,
+            None => 0,
+        };
+        (self.score as u32 as u64) | ((self.depth as u64) << 32) | (bound << 40) | (best << 42)
+    }
+
+    fn unpack(data: u64) -> Self {
+        let bound = match (data >> 40) & 0b11 {
+            0 => Bound::Exact,
+            1 => Bound::Lower,
+            _ => Bound::Upper,
+        };
+        let best = match (data >> 42) & 0x7F {
+            0 => None,
+            n => Some(get_pos(((n - 1) & 0b111) as u8, ((n - 1) >> 3) as u8)),
+        };
+        Entry {
+            score: data as u32 as i32,
+            depth: (data >> 32) as u8,
+            bound,
+            best,
+        }
+    }
+}
+
+pub struct TranspositionTable {
+    entries: Vec<[AtomicU64; 2]>,
+    mask: u64,
+}
+
+impl TranspositionTable {
+    ///
+    /// Creates a table with `2^bits` entries.
+    ///
+    pub fn new(bits: u32) -> Self {
+        let size = 1usize << bits;
+        TranspositionTable {
+            entries: (0..size)
+                .map(|_| [AtomicU64::new(0), AtomicU64::new(0)])
+                .collect(),
+            mask: size as u64 - 1,
+        }
+    }
+
+    fn hash(board: Board, player: bool) -> u64 {
+        let mut h = board.player.wrapping_mul(0x9E3779B97F4A7C15)
+            ^ board
+                .opponent
+                .rotate_left(32)
+                .wrapping_mul(0xC2B2AE3D27D4EB4F)
+            ^ player as u64;
+        h ^= h >> 29;
+        h = h.wrapping_mul(0xBF58476D1CE4E5B9);
+        h ^ (h >> 32)
+    }
+
+    pub fn probe(&self, board: Board, player: bool) -> Option<Entry> {
+        let key = Self::hash(board, player);
+        let [stored, data] = &self.entries[(key & self.mask) as usize];
+        let data = data.load(Ordering::Relaxed);
+        if data != 0 && stored.load(Ordering::Relaxed) ^ data == key {
+            Some(Entry::unpack(data))
+        } else {
+            None
+        }
+    }
+
+    pub fn store(&self, board: Board, player: bool, entry: Entry) {
+        let key = Self::hash(board, player);
+        let [stored, data] = &self.entries[(key & self.mask) as usize];
+        let packed = entry.pack();
+        stored.store(key ^ packed, Ordering::Relaxed);
+        data.store(packed, Ordering::Relaxed);
+    }
+
+    pub fn clear(&self) {
+        for [stored, data] in &self.entries {
+            stored.store(0, Ordering::Relaxed);
+            data.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{board::new_board, proto::Color};
+
+    #[test]
+    fn test_store_and_probe() {
+        let tt = TranspositionTable::new(4);
+        let board = new_board(&Color::Black);
+        let entry = Entry {
+            score: -1234,
+            depth: 7,
+            bound: Bound::Lower,
+            best: Some(get_pos(2, 3)),
+        };
+        tt.store(board, true, entry);
+        assert_eq!(tt.probe(board, true), Some(entry));
+        assert_eq!(tt.probe(board, false), None);
+        tt.clear();
+        assert_eq!(tt.probe(board, true), None);
+    }
+}
@@ -3,9 +3,10 @@
 ///
 #[macro_export]
 macro_rules! popcnt64 {
-    ($e:expr) => {
-        unsafe { core::arch::x86_64::_popcnt64($e as i64) }
-    };
+    ($e:expr) => {{
+        let e = $e;
+        unsafe { core::arch::x86_64::_popcnt64(e as i64) }
+    }};
 }
 
 ///
@@ -13,9 +14,10 @@ macro_rules! popcnt64 {
 ///
 #[macro_export]
 macro_rules! lzcnt64 {
-    ($e:expr) => {
-        unsafe { core::arch::x86_64::_lzcnt_u64($e) }
-    };
+    ($e:expr) => {{
+        let e = $e;
+        unsafe { core::arch::x86_64::_lzcnt_u64(e) }
+    }};
 }
 
 ///
@@ -23,9 +25,10 @@ macro_rules! lzcnt64 {
 ///
 #[macro_export]
 macro_rules! tzcnt64 {
-    ($e:expr) => {
-        unsafe { core::arch::x86_64::_tzcnt_u64($e) }
-    };
+    ($e:expr) => {{
+        let e = $e;
+        unsafe { core::arch::x86_64::_tzcnt_u64(e) }
+    }};
 }
 
 ///
@@ -33,13 +36,87 @@ macro_rules! tzcnt64 {
 ///
 #[macro_export]
 macro_rules! blsmsk64 {
-    ($e:expr) => {
-        unsafe { core::arch::x86_64::_blsmsk_u64($e) }
-    };
+    ($e:expr) => {{
+        let e = $e;
+        unsafe { core::arch::x86_64::_blsmsk_u64(e) }
+    }};
+}
+
+///
+/// A small xorshift64* generator.
+/// It is good enough for self-play and gives the same sequence for the same seed everywhere.
+///
+#[derive(Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // The state must not be zero, from which xorshift only returns zeros.
+        let state = seed ^ 0x9E3779B97F4A7C15;
+        Rng {
+            state: if state == 0 {
+                0x9E3779B97F4A7C15
+            } else {
+                state
+            },
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+
+    ///
+    /// Returns a number in `0..n`.
+    ///
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    ///
+    /// Returns a number in `[0, 1)`.
+    ///
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    ///
+    /// Picks one of the set bits of the view at random.
+    ///
+    pub fn pick(&mut self, view: u64) -> u64 {
+        let mut view = view;
+        for _ in 0..self.below(popcnt64!(view) as u64) {
+            view &= view - 1;
+        }
+        1 << tzcnt64!(view)
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use super::*;
+
+    #[test]
+    fn test_rng_pick() {
+        // The second seed would make the state zero.
+        for seed in [0, 0x9E3779B97F4A7C15] {
+            let mut rng = Rng::new(seed);
+            let mut picked = 0;
+            for _ in 0..100 {
+                let pos = rng.pick(0x0000_1000_0008_0001);
+                assert_eq!(popcnt64!(pos), 1);
+                assert_ne!(pos & 0x0000_1000_0008_0001, 0);
+                picked |= pos;
+            }
+            assert_eq!(picked, 0x0000_1000_0008_0001);
+        }
+    }
+
     #[test]
     fn test_popcnt64() {
         assert_eq!(popcnt64!(0x01010101), 4);
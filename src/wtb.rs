@@ -0,0 +1,399 @@
+//
+// WTHOR game databases.
+//
+// A database consists of `.wtb` files of games and the name files of players (`.jou`)
+// and tournaments (`.trn`) which the games refer to by index.
+// Every file starts with the same 16-byte header. All the numbers are little endian.
+//
+// References:
+//  https://www.ffothello.org/informatique/la-base-wthor/
+//
+
+use crate::{
+    board::{get_pos, get_valid_moves, new_board, put, to_notation, Board, BoardView},
+    popcnt64,
+    proto::{Color, Error},
+    tzcnt64,
+};
+
+pub const HEADER_SIZE: usize = 16;
+pub const RECORD_SIZE: usize = 68;
+
+///
+/// The size of a player name in `.jou` files.
+///
+pub const JOU_RECORD_SIZE: usize = 20;
+
+///
+/// The size of a tournament name in `.trn` files.
+///
+pub const TRN_RECORD_SIZE: usize = 26;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header {
+    pub century: u8,
+    pub year: u8,
+    pub month: u8,
+    pub day: u8,
+    ///
+    /// The number of games in `.wtb` files.
+    ///
+    pub record_num: u32,
+    ///
+    /// The number of names in `.jou` and `.trn` files.
+    ///
+    pub n2: u16,
+    pub game_year: u16,
+    pub board_size: u8,
+    pub record_type: u8,
+    pub depth: u8,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameRecord {
+    pub tournament: u16,
+    pub black: u16,
+    pub white: u16,
+    ///
+    /// The number of black discs at the end of the game.
+    ///
+    pub black_num: u8,
+    ///
+    /// The number of black discs with the perfect play from `depth` empties.
+    ///
+    pub black_best: u8,
+    ///
+    /// Moves without passes.
+    ///
+    pub moves: Vec<BoardView>,
+}
+
+///
+/// The reason why a game cannot be replayed.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InvalidGame {
+    ///
+    /// The move of the index is illegal.
+    ///
+    IllegalMove(usize),
+    ///
+    /// `black_num` disagrees with the final board, which gives the number here.
+    ///
+    BlackNum(u8),
+    ///
+    /// `black_best` is beyond the number of squares.
+    ///
+    BlackBest,
+}
+
+impl std::fmt::Display for InvalidGame {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            InvalidGame::IllegalMove(index) => write!(f, "the move {} is illegal", index + 1),
+            InvalidGame::BlackNum(count) => {
+                write!(f, "black has {} discs on the final board", count)
+            }
+            InvalidGame::BlackBest => write!(f, "the theoretical score is out of range"),
+        }
+    }
+}
+
+impl GameRecord {
+    ///
+    /// Replays the moves through `put`. A side passes only when it has no legal move.
+    /// Returns the final board from the viewpoint of black.
+    ///
+    pub fn replay(&self) -> Result<Board, InvalidGame> {
+        // The board from the viewpoint of the side to move.
+        let mut board = new_board(&Color::Black);
+        let mut black_to_move = true;
+        for (index, &view) in self.moves.iter().enumerate() {
+            if get_valid_moves(board.player, board.opponent) == 0 {
+                board = Board {
+                    player: board.opponent,
+                    opponent: board.player,
+                };
+                black_to_move = !black_to_move;
+            }
+            if get_valid_moves(board.player, board.opponent) & view == 0 {
+                return Err(InvalidGame::IllegalMove(index));
+            }
+            put(view, &mut board.player, &mut board.opponent);
+            board = Board {
+                player: board.opponent,
+                opponent: board.player,
+            };
+            black_to_move = !black_to_move;
+        }
+
+        if black_to_move {
+            Ok(board)
+        } else {
+            Ok(Board {
+                player: board.opponent,
+                opponent: board.player,
+            })
+        }
+    }
+
+    ///
+    /// Checks that the moves are legal and the scores agree with them.
+    /// `black_num` is checked only if the game is over, giving the empty squares to the winner.
+    ///
+    pub fn validate(&self) -> Result<(), InvalidGame> {
+        let board = self.replay()?;
+        if self.black_best > 64 {
+            return Err(InvalidGame::BlackBest);
+        }
+        if get_valid_moves(board.player, board.opponent) != 0
+            || get_valid_moves(board.opponent, board.player) != 0
+        {
+            return Ok(());
+        }
+
+        let black = popcnt64!(board.player) as u8;
+        let white = popcnt64!(board.opponent) as u8;
+        let empty = 64 - black - white;
+        let count = if black > white {
+            black + empty
+        } else if black == white {
+            black + empty / 2
+        } else {
+            black
+        };
+        if count == self.black_num {
+            Ok(())
+        } else {
+            Err(InvalidGame::BlackNum(count))
+        }
+    }
+
+    ///
+    /// The moves like `F5D6C3`.
+    ///
+    pub fn transcript(&self) -> String {
+        self.moves.iter().map(|&view| to_notation(view)).collect()
+    }
+}
+
+///
+/// Decodes a move written as `10 * row + column` where both start from 1.
+///
+fn decode_move(byte: u8) -> Option<BoardView> {
+    let (y, x) = (byte / 10, byte % 10);
+    if (1..=8).contains(&x) && (1..=8).contains(&y) {
+        Some(get_pos(x - 1, y - 1))
+    } else {
+        None
+    }
+}
+
+fn encode_move(view: BoardView) -> u8 {
+    let pos = tzcnt64!(view) as u8;
+    10 * (pos / 8 + 1) + pos % 8 + 1
+}
+
+fn parse_header(bytes: &[u8]) -> Result<Header, Error> {
+    if bytes.len() < HEADER_SIZE {
+        return Err(Error::Parser);
+    }
+    let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+    Ok(Header {
+        century: bytes[0],
+        year: bytes[1],
+        month: bytes[2],
+        day: bytes[3],
+        record_num: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        n2: u16_at(8),
+        game_year: u16_at(10),
+        board_size: bytes[12],
+        record_type: bytes[13],
+        depth: bytes[14],
+    })
+}
+
+fn encode_header(header: &Header, bytes: &mut Vec<u8>) {
+    bytes.extend([header.century, header.year, header.month, header.day]);
+    bytes.extend(header.record_num.to_le_bytes());
+    bytes.extend(header.n2.to_le_bytes());
+    bytes.extend(header.game_year.to_le_bytes());
+    bytes.extend([header.board_size, header.record_type, header.depth, 0]);
+}
+
+pub fn parse_wtb(bytes: &[u8]) -> Result<(Header, Vec<GameRecord>), Error> {
+    let header = parse_header(bytes)?;
+
+    let body = &bytes[HEADER_SIZE..];
+    if body.len() < header.record_num as usize * RECORD_SIZE {
+        return Err(Error::Parser);
+    }
+
+    let records = body
+        .chunks_exact(RECORD_SIZE)
+        .take(header.record_num as usize)
+        .map(|record| GameRecord {
+            tournament: u16::from_le_bytes([record[0], record[1]]),
+            black: u16::from_le_bytes([record[2], record[3]]),
+            white: u16::from_le_bytes([record[4], record[5]]),
+            black_num: record[6],
+            black_best: record[7],
+            moves: record[8..]
+                .iter()
+                .map_while(|&byte| decode_move(byte))
+                .collect(),
+        })
+        .collect();
+
+    Ok((header, records))
+}
+
+pub fn read_wtb(path: &str) -> Result<(Header, Vec<GameRecord>), Error> {
+    parse_wtb(&std::fs::read(path)?)
+}
+
+///
+/// Encodes the games in the `.wtb` format. `record_num` of the header is replaced by the number of the games.
+///
+pub fn encode_wtb(header: &Header, records: &[GameRecord]) -> Vec<u8> {
+    let header = Header {
+        record_num: records.len() as u32,
+        ..header.clone()
+    };
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + records.len() * RECORD_SIZE);
+    encode_header(&header, &mut bytes);
+
+    for record in records {
+        bytes.extend(record.tournament.to_le_bytes());
+        bytes.extend(record.black.to_le_bytes());
+        bytes.extend(record.white.to_le_bytes());
+        bytes.extend([record.black_num, record.black_best]);
+        let mut moves: Vec<u8> = record.moves.iter().map(|&view| encode_move(view)).collect();
+        moves.resize(RECORD_SIZE - 8, 0);
+        bytes.extend(moves);
+    }
+    bytes
+}
+
+pub fn write_wtb(path: &str, header: &Header, records: &[GameRecord]) -> Result<(), Error> {
+    std::fs::write(path, encode_wtb(header, records))?;
+    Ok(())
+}
+
+///
+/// Parses a name file, whose names are NUL-terminated ISO-8859-1 strings of `record_size` bytes.
+///
+pub fn parse_names(bytes: &[u8], record_size: usize) -> Result<(Header, Vec<String>), Error> {
+    let header = parse_header(bytes)?;
+
+    let body = &bytes[HEADER_SIZE..];
+    if body.len() < header.n2 as usize * record_size {
+        return Err(Error::Parser);
+    }
+
+    let names = body
+        .chunks_exact(record_size)
+        .take(header.n2 as usize)
+        .map(|record| {
+            record
+                .iter()
+                .take_while(|&&byte| byte != 0)
+                .map(|&byte| byte as char)
+                .collect::<String>()
+                .trim_end()
+                .to_string()
+        })
+        .collect();
+
+    Ok((header, names))
+}
+
+pub fn read_names(path: &str, record_size: usize) -> Result<(Header, Vec<String>), Error> {
+    parse_names(&std::fs::read(path)?, record_size)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::board::parse_history;
+
+    ///
+    /// Encodes a game as a WTHOR file with a single record.
+    ///
+    pub fn encode_single(moves: &[u8], black_num: u8, black_best: u8) -> Vec<u8> {
+        let mut bytes = vec![20, 24, 1, 1, 1, 0, 0, 0, 0, 0, 0xE8, 0x07, 0, 0, 22, 0];
+        bytes.extend([1, 0, 2, 0, 3, 0, black_num, black_best]);
+        let mut record = moves.to_vec();
+        record.resize(60, 0);
+        bytes.extend(record);
+        bytes
+    }
+
+    fn record(history: &str, black_num: u8) -> GameRecord {
+        GameRecord {
+            tournament: 1,
+            black: 2,
+            white: 3,
+            black_num,
+            black_best: black_num,
+            moves: parse_history(history).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_parse_wtb() {
+        let (header, records) = parse_wtb(&encode_single(&[56, 64, 35], 40, 36)).unwrap();
+        assert_eq!(header.record_num, 1);
+        assert_eq!(header.game_year, 2024);
+        assert_eq!(header.depth, 22);
+        assert_eq!(records[0].black, 2);
+        assert_eq!(records[0].black_best, 36);
+        assert_eq!(
+            records[0].moves,
+            vec![get_pos(5, 4), get_pos(3, 5), get_pos(4, 2)]
+        );
+        assert_eq!(records[0].transcript(), "F5D6E3");
+
+        // The encoder gives back the same bytes.
+        assert_eq!(
+            encode_wtb(&header, &records),
+            encode_single(&[56, 64, 35], 40, 36)
+        );
+    }
+
+    #[test]
+    fn test_parse_names() {
+        let mut bytes = vec![20, 24, 1, 1, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0];
+        for name in [&b"Tamenori Hideshi"[..], b"M\xE9nard Guy"] {
+            let mut record = name.to_vec();
+            record.resize(JOU_RECORD_SIZE, 0);
+            bytes.extend(record);
+        }
+        let (header, names) = parse_names(&bytes, JOU_RECORD_SIZE).unwrap();
+        assert_eq!(header.n2, 2);
+        assert_eq!(names, vec!["Tamenori Hideshi", "Ménard Guy"]);
+
+        assert!(parse_names(&bytes[..40], JOU_RECORD_SIZE).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        // The shortest game, where white is wiped out after 9 moves.
+        // The empty squares are given to black.
+        let game = record("E6F4E3F6G5D6E7F5C5", 64);
+        assert_eq!(game.validate(), Ok(()));
+        assert_eq!(
+            record("E6F4E3F6G5D6E7F5C5", 13).validate(),
+            Err(InvalidGame::BlackNum(64))
+        );
+        assert_eq!(game.replay().map(|board| board.opponent), Ok(0));
+
+        // Games which are not over are not checked by the number of discs.
+        assert_eq!(record("F5D6C3", 0).validate(), Ok(()));
+        assert_eq!(
+            record("F5D6A1", 0).validate(),
+            Err(InvalidGame::IllegalMove(2))
+        );
+        assert_eq!(record("F5", 65).validate(), Err(InvalidGame::BlackBest));
+    }
+}